@@ -0,0 +1,475 @@
+//! winvd - command line interface for the Windows virtual desktops
+//!
+//! Desktops are given by index or GUID, windows by handle in decimal or in
+//! hexadecimal with `0x` prefix. Commands changing a desktop or a window print
+//! its row afterwards, in the format given with `--format`.
+//!
+//! Exit codes are stable, and can be relied on in scripts:
+//!
+//! | Code | Meaning                                                 |
+//! |------|---------------------------------------------------------|
+//! | 0    | Success                                                 |
+//! | 1    | Other error                                             |
+//! | 2    | Invalid command line arguments                          |
+//! | 3    | Desktop not found                                       |
+//! | 4    | Window not found                                        |
+//! | 5    | Window can not be moved between desktops                |
+//! | 6    | Virtual desktop service is not available                |
+//! | 7    | Invalid value, e.g. the wallpaper file does not exist   |
+mod output;
+
+use clap::{Parser, Subcommand};
+use output::{Format, Table};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::ExitCode;
+use windows::{core::GUID, Win32::Foundation::HWND};
+use winvd::*;
+
+const EXIT_FAILED: u8 = 1;
+const EXIT_DESKTOP_NOT_FOUND: u8 = 3;
+const EXIT_WINDOW_NOT_FOUND: u8 = 4;
+const EXIT_WINDOW_NOT_MOVABLE: u8 = 5;
+const EXIT_SERVICE_NOT_AVAILABLE: u8 = 6;
+const EXIT_INVALID_VALUE: u8 = 7;
+
+const DESKTOP_COLUMNS: &[&str] = &["index", "id", "name", "wallpaper", "current"];
+const EVENT_COLUMNS: &[&str] = &["event", "desktop", "window", "value", "previous"];
+const WINDOW_COLUMNS: &[&str] = &["window", "app_id", "desktop", "pinned", "app_pinned"];
+
+#[derive(Parser)]
+#[command(name = "winvd", version, about = "Manage Windows virtual desktops")]
+struct Cli {
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Human, global = true)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List all desktops
+    List,
+
+    /// Show the current desktop
+    Current,
+
+    /// Switch to the desktop
+    Switch {
+        #[arg(value_parser = parse_desktop)]
+        desktop: Desktop,
+    },
+
+    /// Create a new desktop at the end
+    Create {
+        /// Name of the new desktop
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Remove the desktop, its windows are moved to the fallback desktop
+    Remove {
+        #[arg(value_parser = parse_desktop)]
+        desktop: Desktop,
+
+        /// Defaults to the desktop left of the removed one
+        #[arg(long, value_parser = parse_desktop)]
+        fallback: Option<Desktop>,
+    },
+
+    /// Rename the desktop
+    Rename {
+        #[arg(value_parser = parse_desktop)]
+        desktop: Desktop,
+        name: String,
+    },
+
+    /// Show the wallpaper of the desktop, or set it if path is given
+    Wallpaper {
+        #[arg(value_parser = parse_desktop)]
+        desktop: Desktop,
+        path: Option<String>,
+    },
+
+    /// Move the window to the desktop
+    MoveWindow {
+        #[arg(value_parser = parse_hwnd)]
+        window: HWND,
+
+        #[arg(value_parser = parse_desktop)]
+        desktop: Desktop,
+    },
+
+    /// Pin the window on all desktops
+    Pin {
+        #[arg(value_parser = parse_hwnd)]
+        window: HWND,
+
+        /// Pin all windows of the app
+        #[arg(long)]
+        app: bool,
+    },
+
+    /// Unpin the window
+    Unpin {
+        #[arg(value_parser = parse_hwnd)]
+        window: HWND,
+
+        /// Unpin all windows of the app
+        #[arg(long)]
+        app: bool,
+    },
+
+    /// Print desktop events until interrupted
+    Watch,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command, cli.format) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(er) => {
+            eprintln!("error: {}", er);
+            ExitCode::from(exit_code(&er))
+        }
+    }
+}
+
+fn run(command: Command, format: Format) -> Result<()> {
+    let mut table = Table::new(match command {
+        Command::MoveWindow { .. } | Command::Pin { .. } | Command::Unpin { .. } => WINDOW_COLUMNS,
+        _ => DESKTOP_COLUMNS,
+    });
+    match command {
+        Command::List => {
+            let current = get_current_desktop()?;
+            for desktop in get_desktops()? {
+                table.push(desktop_row(&desktop, &current)?);
+            }
+        }
+        Command::Current => {
+            let current = get_current_desktop()?;
+            table.push(desktop_row(&current, &current)?);
+        }
+        Command::Switch { desktop } => {
+            switch_desktop(desktop)?;
+            table.push(desktop_row(&desktop, &desktop)?);
+        }
+        Command::Create { name } => {
+            let desktop = create_desktop()?;
+            if let Some(name) = name {
+                desktop.set_name(&name)?;
+            }
+            table.push(desktop_row(&desktop, &get_current_desktop()?)?);
+        }
+        Command::Remove { desktop, fallback } => {
+            let fallback = match fallback {
+                Some(fallback) => fallback,
+                None => get_adjacent_desktop(desktop, Direction::Left)?
+                    .or(get_adjacent_desktop(desktop, Direction::Right)?)
+                    .ok_or(Error::RemoveDesktopFailed)?,
+            };
+
+            // Row of the removed desktop as it was
+            let row = desktop_row(&desktop, &get_current_desktop()?)?;
+            remove_desktop(desktop, fallback)?;
+            table.push(row);
+        }
+        Command::Rename { desktop, name } => {
+            desktop.set_name(&name)?;
+            table.push(desktop_row(&desktop, &get_current_desktop()?)?);
+        }
+        Command::Wallpaper {
+            desktop,
+            path: Some(path),
+        } => {
+            desktop.set_wallpaper(&path)?;
+            table.push(desktop_row(&desktop, &get_current_desktop()?)?);
+        }
+        Command::Wallpaper {
+            desktop,
+            path: None,
+        } => table.push(desktop_row(&desktop, &get_current_desktop()?)?),
+        Command::MoveWindow { window, desktop } => {
+            move_window_to_desktop(desktop, window)?;
+            table.push(window_row(window)?);
+        }
+        Command::Pin { window, app } => {
+            match app {
+                false => pin_window(window)?,
+                true => pin_app(window)?,
+            }
+            table.push(window_row(window)?);
+        }
+        Command::Unpin { window, app } => {
+            match app {
+                false => unpin_window(window)?,
+                true => unpin_app(window)?,
+            }
+            table.push(window_row(window)?);
+        }
+        Command::Watch => return watch(format),
+    }
+    print!("{}", table.render(format));
+    Ok(())
+}
+
+fn watch(format: Format) -> Result<()> {
+    let table = Table::new(EVENT_COLUMNS);
+    let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+    let _listener = listen_desktop_events(tx)?;
+
+    let mut stdout = std::io::stdout();
+    if format == Format::Csv {
+        print!("{}", table.csv_header());
+    }
+    for event in rx {
+        print!("{}", table.render_line(format, &event_row(&event)));
+        let _ = stdout.flush();
+    }
+    Ok(())
+}
+
+fn desktop_row(desktop: &Desktop, current: &Desktop) -> Result<Vec<Value>> {
+    let id = desktop.get_id()?;
+    Ok(vec![
+        json!(desktop.get_index()?),
+        json!(format!("{:?}", id)),
+        supported(desktop.get_name())?,
+        supported(desktop.get_wallpaper())?,
+        json!(current.get_id()? == id),
+    ])
+}
+
+/// Desktop is null for pinned windows, which are shown on all desktops
+fn window_row(window: HWND) -> Result<Vec<Value>> {
+    let pinned = is_pinned_window(window)?;
+    let app_pinned = is_pinned_app(window)?;
+    let desktop = match pinned || app_pinned {
+        true => Value::Null,
+        false => json!(format!("{:?}", get_desktop_by_window(window)?.get_id()?)),
+    };
+    Ok(vec![
+        json!(window.0),
+        json!(get_app_id(window)?),
+        desktop,
+        json!(pinned),
+        json!(app_pinned),
+    ])
+}
+
+/// Value of the result, null if the build does not support it
+fn supported<T: Into<Value>>(result: Result<T>) -> Result<Value> {
+    match result {
+        Ok(value) => Ok(value.into()),
+        Err(Error::NotSupported) => Ok(Value::Null),
+        Err(err) => Err(err),
+    }
+}
+
+fn event_row(event: &DesktopEvent) -> Vec<Value> {
+    let id = |desktop: &Desktop| match desktop.get_id() {
+        Ok(id) => json!(format!("{:?}", id)),
+        Err(_) => Value::Null,
+    };
+    let (name, desktop, window, value, previous) = match event {
+        DesktopEvent::DesktopCreated(desktop) => ("DesktopCreated", id(desktop), None, None, None),
+        DesktopEvent::DesktopDestroyBegin {
+            destroyed,
+            fallback,
+        } => (
+            "DesktopDestroyBegin",
+            id(destroyed),
+            None,
+            Some(id(fallback)),
+            None,
+        ),
+        DesktopEvent::DesktopDestroyFailed {
+            destroyed,
+            fallback,
+        } => (
+            "DesktopDestroyFailed",
+            id(destroyed),
+            None,
+            Some(id(fallback)),
+            None,
+        ),
+        DesktopEvent::DesktopDestroyed {
+            destroyed,
+            fallback,
+        } => (
+            "DesktopDestroyed",
+            id(destroyed),
+            None,
+            Some(id(fallback)),
+            None,
+        ),
+        DesktopEvent::DesktopChanged { new, old } => {
+            ("DesktopChanged", id(new), None, None, Some(id(old)))
+        }
+        DesktopEvent::DesktopSwitched(desktop) => {
+            ("DesktopSwitched", id(desktop), None, None, None)
+        }
+        DesktopEvent::RemoteDesktopConnected(desktop) => {
+            ("RemoteDesktopConnected", id(desktop), None, None, None)
+        }
+        DesktopEvent::DesktopNameChanged(desktop, name) => (
+            "DesktopNameChanged",
+            id(desktop),
+            None,
+            Some(json!(name)),
+            None,
+        ),
+        DesktopEvent::DesktopWallpaperChanged(desktop, path) => (
+            "DesktopWallpaperChanged",
+            id(desktop),
+            None,
+            Some(json!(path)),
+            None,
+        ),
+        DesktopEvent::DesktopMoved {
+            desktop,
+            old_index,
+            new_index,
+        } => (
+            "DesktopMoved",
+            id(desktop),
+            None,
+            Some(json!(new_index)),
+            Some(json!(old_index)),
+        ),
+        DesktopEvent::WindowChanged(window) => {
+            ("WindowChanged", Value::Null, Some(window), None, None)
+        }
+        DesktopEvent::WindowMovedToDesktop { window, from, to } => (
+            "WindowMovedToDesktop",
+            id(to),
+            Some(window),
+            None,
+            Some(id(from)),
+        ),
+        DesktopEvent::WindowPinned { window, from } => (
+            "WindowPinned",
+            Value::Null,
+            Some(window),
+            None,
+            Some(id(from)),
+        ),
+        DesktopEvent::WindowUnpinned { window, to } => {
+            ("WindowUnpinned", id(to), Some(window), None, None)
+        }
+        DesktopEvent::WindowAppeared { window, desktop } => (
+            "WindowAppeared",
+            desktop.as_ref().map_or(Value::Null, id),
+            Some(window),
+            None,
+            None,
+        ),
+        DesktopEvent::ShellDisconnected => ("ShellDisconnected", Value::Null, None, None, None),
+        DesktopEvent::ShellReconnected => ("ShellReconnected", Value::Null, None, None, None),
+        DesktopEvent::ListenerRegistrationFailed { error } => (
+            "ListenerRegistrationFailed",
+            Value::Null,
+            None,
+            Some(json!(error.to_string())),
+            None,
+        ),
+    };
+    vec![
+        json!(name),
+        desktop,
+        window.map_or(Value::Null, |window| json!(window.hwnd().0)),
+        value.unwrap_or(Value::Null),
+        previous.unwrap_or(Value::Null),
+    ]
+}
+
+fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::DesktopNotFound(_) | Error::InvalidDesktopIndex(_) => EXIT_DESKTOP_NOT_FOUND,
+        Error::WindowNotFound(_) => EXIT_WINDOW_NOT_FOUND,
+        Error::WindowNotMovable(_) => EXIT_WINDOW_NOT_MOVABLE,
+        Error::ClassNotRegistered
+        | Error::RpcServerNotAvailable
+        | Error::ComNotInitialized
+        | Error::ComObjectNotConnected
+        | Error::BackendNotAvailable
+        | Error::UnsupportedBuild { .. }
+        | Error::BuildDetectionFailed(_) => EXIT_SERVICE_NOT_AVAILABLE,
+        Error::InvalidWallpaperPath | Error::InvalidLayout(_) => EXIT_INVALID_VALUE,
+        _ => EXIT_FAILED,
+    }
+}
+
+/// Desktop by index, or by GUID e.g. `3F07F4BE-B107-441A-AF0F-39D82529072C`
+fn parse_desktop(value: &str) -> std::result::Result<Desktop, String> {
+    if let Ok(index) = value.parse::<u32>() {
+        return Ok(get_desktop(index));
+    }
+    let hex = value
+        .trim_matches(|c| c == '{' || c == '}')
+        .replace('-', "");
+    match u128::from_str_radix(&hex, 16) {
+        Ok(guid) if hex.len() == 32 => Ok(get_desktop(GUID::from_u128(guid))),
+        _ => Err(format!("{} is not a desktop index or GUID", value)),
+    }
+}
+
+/// Window handle in decimal, or hexadecimal with `0x` prefix
+fn parse_hwnd(value: &str) -> std::result::Result<HWND, String> {
+    let parsed = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => isize::from_str_radix(hex, 16),
+        None => value.parse::<isize>(),
+    };
+    parsed
+        .map(HWND)
+        .map_err(|_| format!("{} is not a window handle", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_desktop() {
+        assert_eq!(parse_desktop("2").unwrap(), get_desktop(2));
+        let guid = GUID::from_u128(0x3F07F4BE_B107_441A_AF0F_39D82529072C);
+        assert_eq!(
+            parse_desktop("3F07F4BE-B107-441A-AF0F-39D82529072C").unwrap(),
+            get_desktop(guid)
+        );
+        assert_eq!(
+            parse_desktop("{3f07f4be-b107-441a-af0f-39d82529072c}").unwrap(),
+            get_desktop(guid)
+        );
+        assert!(parse_desktop("Mail").is_err());
+    }
+
+    #[test]
+    fn test_parse_hwnd() {
+        assert_eq!(parse_hwnd("1234").unwrap(), HWND(1234));
+        assert_eq!(parse_hwnd("0x4D2").unwrap(), HWND(1234));
+        assert!(parse_hwnd("notepad").is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(
+            exit_code(&Error::DesktopNotFound(ErrorTarget::DesktopIndex(5))),
+            3
+        );
+        assert_eq!(exit_code(&Error::WindowNotFound(HWND(1234))), 4);
+        assert_eq!(exit_code(&Error::WindowNotMovable(HWND(1234))), 5);
+        assert_eq!(exit_code(&Error::BackendNotAvailable), 6);
+        assert_eq!(exit_code(&Error::InvalidWallpaperPath), 7);
+        assert_eq!(exit_code(&Error::CreateDesktopFailed), 1);
+    }
+}
@@ -0,0 +1,165 @@
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns
+    Human,
+
+    /// Array of objects, events are written one object per line
+    Json,
+
+    /// Comma separated values with a header row
+    Csv,
+}
+
+/// Rows with named columns, printed in the selected format
+pub struct Table {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    pub fn new(columns: &'static [&'static str]) -> Self {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Value>) {
+        self.rows.push(row);
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Human => {
+                let rows = std::iter::once(self.columns.iter().map(|c| c.to_uppercase()).collect())
+                    .chain(
+                        self.rows
+                            .iter()
+                            .map(|row| row.iter().map(display).collect::<Vec<_>>()),
+                    )
+                    .collect::<Vec<Vec<String>>>();
+                let widths = (0..self.columns.len())
+                    .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+                    .collect::<Vec<_>>();
+                rows.iter()
+                    .map(|row| {
+                        let line = row
+                            .iter()
+                            .zip(&widths)
+                            .map(|(value, width)| format!("{:width$}", value, width = width))
+                            .collect::<Vec<_>>()
+                            .join("  ");
+                        format!("{}\n", line.trim_end())
+                    })
+                    .collect()
+            }
+            Format::Json => {
+                let objects = self.rows.iter().map(|row| self.object(row)).collect();
+                format!("{:#}\n", Value::Array(objects))
+            }
+            Format::Csv => std::iter::once(self.csv_header())
+                .chain(self.rows.iter().map(|row| csv_line(row)))
+                .collect(),
+        }
+    }
+
+    /// Header line for CSV streams, see `render_line`
+    pub fn csv_header(&self) -> String {
+        let header = self
+            .columns
+            .iter()
+            .map(|c| Value::String(c.to_string()))
+            .collect::<Vec<_>>();
+        csv_line(&header)
+    }
+
+    /// Single row for streaming output, e.g. events
+    pub fn render_line(&self, format: Format, row: &[Value]) -> String {
+        match format {
+            Format::Human => {
+                let values = row
+                    .iter()
+                    .map(display)
+                    .filter(|v| !v.is_empty())
+                    .collect::<Vec<_>>();
+                format!("{}\n", values.join("  "))
+            }
+            Format::Json => format!("{}\n", self.object(row)),
+            Format::Csv => csv_line(row),
+        }
+    }
+
+    fn object(&self, row: &[Value]) -> Value {
+        let map = self
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| (column.to_string(), value.clone()))
+            .collect::<Map<_, _>>();
+        Value::Object(map)
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn csv_line(row: &[Value]) -> String {
+    let fields = row
+        .iter()
+        .map(|value| {
+            let field = display(value);
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("{}\n", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn table() -> Table {
+        let mut table = Table::new(&["index", "name", "current"]);
+        table.push(vec![json!(0), json!("Mail"), json!(true)]);
+        table.push(vec![json!(1), json!("Code, \"rust\""), json!(false)]);
+        table
+    }
+
+    #[test]
+    fn test_render_human() {
+        assert_eq!(
+            table().render(Format::Human),
+            "INDEX  NAME          CURRENT\n\
+             0      Mail          true\n\
+             1      Code, \"rust\"  false\n"
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json: Value = serde_json::from_str(&table().render(Format::Json)).unwrap();
+        assert_eq!(json[1]["name"], "Code, \"rust\"");
+        assert_eq!(json[0]["current"], true);
+    }
+
+    #[test]
+    fn test_render_csv() {
+        assert_eq!(
+            table().render(Format::Csv),
+            "index,name,current\n0,Mail,true\n1,\"Code, \"\"rust\"\"\",false\n"
+        );
+    }
+}
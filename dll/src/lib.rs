@@ -216,6 +216,12 @@ pub extern "C" fn RemoveDesktop(remove_desktop_number: i32, fallback_desktop_num
     remove_desktop(remove_desktop_number, fallback_desktop_number).map_or(-1, |_| 1)
 }
 
+#[no_mangle]
+pub extern "C" fn SetWallpaperForAllDesktops(in_path_ptr: *const i8) -> i32 {
+    let path_str = unsafe { CStr::from_ptr(in_path_ptr).to_string_lossy() };
+    set_wallpaper_for_all(&path_str).map_or(-1, |desktops| desktops.len() as i32)
+}
+
 #[no_mangle]
 pub extern "C" fn RestartVirtualDesktopAccessor() {
     // ?
@@ -242,7 +248,8 @@ mod log {
     pub(crate) fn log_output(_s: &str) {}
 }
 
-#[cfg(test)]
+// These require the Windows shell
+#[cfg(all(test, windows))]
 mod tests {
     use super::*;
 
@@ -0,0 +1,441 @@
+//! Async variants of the desktop functions, enabled with the `async` feature
+//!
+//! Calls are sent to a `ComThread` which makes the COM calls in the order the
+//! futures were created, the threads of the async runtime are never blocked
+//! by the shell. The call is made even if the future is not polled.
+//!
+//! The thread selected with `Backend::ComThread` is used, otherwise a shared
+//! one is started on the first call.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # async fn run() -> winvd::Result<()> {
+//! winvd::r#async::switch_desktop(1).await?;
+//! let name = winvd::r#async::get_desktop_name(1).await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    get_backend, Backend, Capabilities, ComThread, Desktop, DesktopWindow, Direction, EdgePolicy,
+    Error, Result, Window,
+};
+use futures_channel::oneshot;
+use std::future::Future;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use windows::core::GUID;
+
+// Started for the selected backend, unless it is `Backend::ComThread`
+static SHARED_THREAD: Mutex<Option<Arc<ComThread>>> = Mutex::new(None);
+
+/// Future of a call made on the COM thread
+///
+/// If the call panics, the panic is resumed when the future is polled. If the
+/// COM thread can not run the call, the future resolves to an error.
+#[derive(Debug)]
+pub struct ComFuture<T> {
+    receiver: oneshot::Receiver<std::thread::Result<Result<T>>>,
+}
+
+impl<T> Future for ComFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.receiver).poll(cx) {
+            Poll::Ready(Ok(Ok(result))) => Poll::Ready(result),
+            Poll::Ready(Ok(Err(panic))) => resume_unwind(panic),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(Error::BackendNotAvailable)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The selected `ComThread`, or the shared one for the selected backend
+///
+/// The shared thread is started without waiting for it, and a replaced one is
+/// dropped on another thread, as dropping it waits for its jobs to finish.
+fn com_thread() -> Result<Arc<ComThread>> {
+    let backend = match get_backend() {
+        Backend::ComThread(thread) => return Ok(thread),
+        backend => backend,
+    };
+    let mut shared = SHARED_THREAD.lock().unwrap_or_else(|e| e.into_inner());
+    let is_for_backend = |thread: &ComThread| match (thread.backend(), &backend) {
+        (Backend::Com, Backend::Com) => true,
+        (Backend::Simulated(a), Backend::Simulated(b)) => Arc::ptr_eq(a, b),
+        _ => false,
+    };
+    if let Some(thread) = shared.as_ref() {
+        if is_for_backend(thread) && !thread.is_finished() {
+            return Ok(thread.clone());
+        }
+    }
+    let thread = Arc::new(ComThread::start_in_background(backend)?);
+    let old = shared.replace(thread.clone());
+    drop(shared);
+    if let Some(old) = old {
+        // If the thread can not be spawned, the old one is dropped here
+        let _ = std::thread::Builder::new()
+            .name("winvd-com-drop".to_owned())
+            .spawn(move || drop(old));
+    }
+    Ok(thread)
+}
+
+/// Run the function on the COM thread
+fn spawn<F, T>(f: F) -> ComFuture<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let thread = match com_thread() {
+        Ok(thread) => thread,
+        Err(er) => {
+            let _ = tx.send(Ok(Err(er)));
+            return ComFuture { receiver: rx };
+        }
+    };
+
+    // If the thread is gone the job is dropped, and the future resolves to
+    // an error
+    let _ = thread.post(move |_| {
+        let _ = tx.send(catch_unwind(AssertUnwindSafe(f)));
+    });
+    ComFuture { receiver: rx }
+}
+
+/// Get the GUID of the desktop by index or GUID
+pub fn get_desktop_id<T>(desktop: T) -> ComFuture<GUID>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || desktop.get_id())
+}
+
+/// Get the index of the desktop by index or GUID
+pub fn get_desktop_index<T>(desktop: T) -> ComFuture<u32>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || desktop.get_index())
+}
+
+/// Get desktop name by index or GUID
+pub fn get_desktop_name<T>(desktop: T) -> ComFuture<String>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || desktop.get_name())
+}
+
+/// Set desktop name by index or GUID
+pub fn set_desktop_name<T>(desktop: T, name: &str) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    let name = name.to_owned();
+    spawn(move || desktop.set_name(&name))
+}
+
+/// Get desktop wallpaper path by index or GUID
+pub fn get_desktop_wallpaper<T>(desktop: T) -> ComFuture<String>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || desktop.get_wallpaper())
+}
+
+/// Set desktop wallpaper path by index or GUID
+pub fn set_desktop_wallpaper<T>(desktop: T, path: &str) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    let path = path.to_owned();
+    spawn(move || desktop.set_wallpaper(&path))
+}
+
+/// Get the windows on the desktop, see `Desktop::get_windows`
+pub fn get_desktop_windows<T>(desktop: T) -> ComFuture<Vec<Window>>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || desktop.get_windows())
+}
+
+/// Get the windows on the desktop topmost first, see
+/// `Desktop::get_windows_by_zorder`
+pub fn get_desktop_windows_by_zorder<T>(desktop: T) -> ComFuture<Vec<Window>>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || desktop.get_windows_by_zorder())
+}
+
+/// Switch desktop by index or GUID
+pub fn switch_desktop<T>(desktop: T) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || crate::switch_desktop(desktop))
+}
+
+/// Remove desktop by index or GUID
+pub fn remove_desktop<T>(desktop: T, fallback_desktop: T) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    let fallback_desktop = fallback_desktop.into();
+    spawn(move || crate::remove_desktop(desktop, fallback_desktop))
+}
+
+/// Move desktop to the new index
+pub fn move_desktop<T>(desktop: T, new_index: u32) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || crate::move_desktop(desktop, new_index))
+}
+
+/// Swap the positions of two desktops
+pub fn swap_desktops<T>(desktop_a: T, desktop_b: T) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+{
+    let desktop_a = desktop_a.into();
+    let desktop_b = desktop_b.into();
+    spawn(move || crate::swap_desktops(desktop_a, desktop_b))
+}
+
+/// Sort desktops by name, see `sort_desktops_by_name`
+pub fn sort_desktops_by_name() -> ComFuture<()> {
+    spawn(crate::sort_desktops_by_name)
+}
+
+/// Get the desktop next to the desktop by index or GUID
+pub fn get_adjacent_desktop<T>(desktop: T, direction: Direction) -> ComFuture<Option<Desktop>>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || crate::get_adjacent_desktop(desktop, direction))
+}
+
+/// Switch to the desktop right of the current desktop
+pub fn switch_desktop_next(policy: EdgePolicy) -> ComFuture<Desktop> {
+    spawn(move || crate::switch_desktop_next(policy))
+}
+
+/// Switch to the desktop left of the current desktop
+pub fn switch_desktop_previous(policy: EdgePolicy) -> ComFuture<Desktop> {
+    spawn(move || crate::switch_desktop_previous(policy))
+}
+
+/// Set wallpaper of all desktops
+pub fn set_wallpaper_for_all(path: &str) -> ComFuture<Vec<Desktop>> {
+    let path = path.to_owned();
+    spawn(move || crate::set_wallpaper_for_all(&path))
+}
+
+/// Is window on desktop by index or GUID
+pub fn is_window_on_desktop<T, W>(desktop: T, window: W) -> ComFuture<bool>
+where
+    T: Into<Desktop>,
+    W: Into<Window>,
+{
+    let desktop = desktop.into();
+    let window = window.into();
+    spawn(move || crate::is_window_on_desktop(desktop, window))
+}
+
+/// Can the window be moved to another desktop
+pub fn can_move_window<W>(window: W) -> ComFuture<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::can_move_window(window))
+}
+
+/// Move window to desktop by index or GUID
+pub fn move_window_to_desktop<T, W>(desktop: T, window: W) -> ComFuture<()>
+where
+    T: Into<Desktop>,
+    W: Into<Window>,
+{
+    let desktop = desktop.into();
+    let window = window.into();
+    spawn(move || crate::move_window_to_desktop(desktop, window))
+}
+
+/// Create desktop
+pub fn create_desktop() -> ComFuture<Desktop> {
+    spawn(crate::create_desktop)
+}
+
+/// Get current desktop
+pub fn get_current_desktop() -> ComFuture<Desktop> {
+    spawn(crate::get_current_desktop)
+}
+
+/// Get all desktops
+pub fn get_desktops() -> ComFuture<Vec<Desktop>> {
+    spawn(crate::get_desktops)
+}
+
+/// Get desktop by window
+pub fn get_desktop_by_window<W>(window: W) -> ComFuture<Desktop>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::get_desktop_by_window(window))
+}
+
+/// Get all windows with their desktop and pinned state
+pub fn get_all_windows() -> ComFuture<Vec<DesktopWindow>> {
+    spawn(crate::get_all_windows)
+}
+
+/// Get the focused window and its desktop
+pub fn get_focused_window() -> ComFuture<Option<DesktopWindow>> {
+    spawn(crate::get_focused_window)
+}
+
+/// Get the window that was active last on the desktop by index or GUID
+pub fn get_last_active_window<T>(desktop: T) -> ComFuture<Option<DesktopWindow>>
+where
+    T: Into<Desktop>,
+{
+    let desktop = desktop.into();
+    spawn(move || crate::get_last_active_window(desktop))
+}
+
+/// Activate window, switches to the desktop of the window
+pub fn activate_window<W>(window: W) -> ComFuture<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::activate_window(window))
+}
+
+/// Get the application user model ID of the window
+pub fn get_app_id<W>(window: W) -> ComFuture<String>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::get_app_id(window))
+}
+
+/// Is the app pinned by application user model ID
+pub fn is_app_id_pinned(app_id: &str) -> ComFuture<bool> {
+    let app_id = app_id.to_owned();
+    spawn(move || crate::is_app_id_pinned(&app_id))
+}
+
+/// Pin the app by application user model ID
+pub fn pin_app_id(app_id: &str) -> ComFuture<()> {
+    let app_id = app_id.to_owned();
+    spawn(move || crate::pin_app_id(&app_id))
+}
+
+/// Unpin the app by application user model ID
+pub fn unpin_app_id(app_id: &str) -> ComFuture<()> {
+    let app_id = app_id.to_owned();
+    spawn(move || crate::unpin_app_id(&app_id))
+}
+
+/// Get the windows of the app by application user model ID
+pub fn get_windows_by_app_id(app_id: &str) -> ComFuture<Vec<DesktopWindow>> {
+    let app_id = app_id.to_owned();
+    spawn(move || crate::get_windows_by_app_id(&app_id))
+}
+
+/// Get number of desktops
+pub fn get_desktop_count() -> ComFuture<u32> {
+    spawn(crate::get_desktop_count)
+}
+
+/// Is window on current desktop
+pub fn is_window_on_current_desktop<W>(window: W) -> ComFuture<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::is_window_on_current_desktop(window))
+}
+
+/// Is window pinned?
+pub fn is_pinned_window<W>(window: W) -> ComFuture<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::is_pinned_window(window))
+}
+
+/// Pin window
+pub fn pin_window<W>(window: W) -> ComFuture<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::pin_window(window))
+}
+
+/// Unpin window
+pub fn unpin_window<W>(window: W) -> ComFuture<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::unpin_window(window))
+}
+
+/// Is pinned app
+pub fn is_pinned_app<W>(window: W) -> ComFuture<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::is_pinned_app(window))
+}
+
+/// Pin app
+pub fn pin_app<W>(window: W) -> ComFuture<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::pin_app(window))
+}
+
+/// Unpin app
+pub fn unpin_app<W>(window: W) -> ComFuture<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    spawn(move || crate::unpin_app(window))
+}
+
+/// Get the operations and events supported by the shell
+pub fn get_capabilities() -> ComFuture<Capabilities> {
+    spawn(crate::get_capabilities)
+}
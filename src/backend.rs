@@ -0,0 +1,265 @@
+/// Backend abstraction for the virtual desktop shell
+///
+/// All public functions of this crate go through `with_com_objects`, which
+/// hands them the currently selected backend. By default it is the COM
+/// backend talking to explorer.exe, but it can be swapped at runtime to the
+/// in-memory `SimulatedShell`, e.g. for testing on machines without Windows 11.
+use super::*;
+use crate::retry::{current_retry_policy, record_retries, run_scoped};
+use std::sync::{Arc, RwLock};
+use windows::core::GUID;
+
+#[cfg(windows)]
+use crate::comobjects::ComBackend;
+
+/// Operations of the virtual desktop shell
+///
+/// Implemented by the COM backend and by `SimulatedShell`.
+pub trait VirtualDesktopBackend {
+    /// Get the index of the desktop
+    fn get_desktop_index(&self, desktop: &Desktop) -> Result<u32>;
+
+    /// Get the GUID of the desktop
+    fn get_desktop_id(&self, desktop: &Desktop) -> Result<GUID>;
+
+    /// Get all desktops in order
+    fn get_desktops(&self) -> Result<Vec<Desktop>>;
+
+    /// Get desktop count
+    fn get_desktop_count(&self) -> Result<u32>;
+
+    /// Get current desktop
+    fn get_current_desktop(&self) -> Result<Desktop>;
+
+    /// Switch to the desktop
+    fn switch_desktop(&self, desktop: &Desktop) -> Result<()>;
+
+    /// Create a new desktop at the end
+    fn create_desktop(&self) -> Result<Desktop>;
+
+    /// Remove the desktop, windows are moved to the fallback desktop
+    fn remove_desktop(&self, desktop: &Desktop, fallback_desktop: &Desktop) -> Result<()>;
+
+    /// Move the desktop to the index, other desktops are shifted
+    fn move_desktop(&self, desktop: &Desktop, index: u32) -> Result<()>;
+
+    /// Get the desktop next to the desktop in the direction, `None` if the
+    /// desktop is first or last
+    fn get_adjacent_desktop(
+        &self,
+        desktop: &Desktop,
+        direction: Direction,
+    ) -> Result<Option<Desktop>>;
+
+    /// Get desktop name
+    fn get_desktop_name(&self, desktop: &Desktop) -> Result<String>;
+
+    /// Set desktop name
+    fn set_desktop_name(&self, desktop: &Desktop, name: &str) -> Result<()>;
+
+    /// Get desktop wallpaper path
+    fn get_desktop_wallpaper(&self, desktop: &Desktop) -> Result<String>;
+
+    /// Set desktop wallpaper path
+    fn set_desktop_wallpaper(&self, desktop: &Desktop, path: &str) -> Result<()>;
+
+    /// Set wallpaper path of all desktops
+    fn set_wallpaper_for_all(&self, path: &str) -> Result<()>;
+
+    /// Is window on the desktop
+    fn is_window_on_desktop(&self, window: &Window, desktop: &Desktop) -> Result<bool>;
+
+    /// Is window on the current desktop
+    fn is_window_on_current_desktop(&self, window: &Window) -> Result<bool>;
+
+    /// Can the window be moved to another desktop
+    fn can_move_window(&self, window: &Window) -> Result<bool>;
+
+    /// Move window to the desktop, fails with `Error::WindowNotMovable` if
+    /// the window can not be moved
+    fn move_window_to_desktop(&self, window: &Window, desktop: &Desktop) -> Result<()>;
+
+    /// Get the desktop of the window
+    fn get_desktop_by_window(&self, window: &Window) -> Result<Desktop>;
+
+    /// Get all windows that are on some desktop or pinned
+    fn get_all_windows(&self) -> Result<Vec<DesktopWindow>>;
+
+    /// Get all windows that are on some desktop or pinned, topmost first
+    fn get_all_windows_by_zorder(&self) -> Result<Vec<DesktopWindow>>;
+
+    /// Get the window that has the focus, `None` if no window has it
+    fn get_focused_window(&self) -> Result<Option<DesktopWindow>>;
+
+    /// Get the window on the desktop that was active last, pinned windows are
+    /// not considered
+    fn get_last_active_window(&self, desktop: &Desktop) -> Result<Option<DesktopWindow>>;
+
+    /// Get the title of the window
+    fn get_window_title(&self, window: &Window) -> Result<String>;
+
+    /// Get snapshot of the window state
+    fn get_window_info(&self, window: &Window) -> Result<WindowInfo>;
+
+    /// Switch to the desktop of the window and bring the window to the front,
+    /// pinned windows are activated on the current desktop
+    fn activate_window(&self, window: &Window) -> Result<()>;
+
+    /// Is window pinned
+    fn is_pinned_window(&self, window: &Window) -> Result<bool>;
+
+    /// Pin window
+    fn pin_window(&self, window: &Window) -> Result<()>;
+
+    /// Unpin window
+    fn unpin_window(&self, window: &Window) -> Result<()>;
+
+    /// Is the app of the window pinned
+    fn is_pinned_app(&self, window: &Window) -> Result<bool>;
+
+    /// Pin the app of the window
+    fn pin_app(&self, window: &Window) -> Result<()>;
+
+    /// Unpin the app of the window
+    fn unpin_app(&self, window: &Window) -> Result<()>;
+
+    /// Get the application user model ID of the window
+    fn get_app_id(&self, window: &Window) -> Result<String>;
+
+    /// Is the app pinned
+    fn is_app_id_pinned(&self, app_id: &str) -> Result<bool>;
+
+    /// Pin the app, also the windows opened later are pinned
+    fn pin_app_id(&self, app_id: &str) -> Result<()>;
+
+    /// Unpin the app
+    fn unpin_app_id(&self, app_id: &str) -> Result<()>;
+
+    /// Get windows of the app
+    fn get_windows_by_app_id(&self, app_id: &str) -> Result<Vec<DesktopWindow>>;
+
+    /// Register a callback for desktop events, returns a cookie for
+    /// unregistering.
+    ///
+    /// Only the events in the mask are created and sent. The callback may be
+    /// called from any thread.
+    fn register_for_notifications(
+        &self,
+        sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+        mask: EventMask,
+    ) -> Result<u32>;
+
+    /// Unregister the callback registered with `register_for_notifications`
+    fn unregister_for_notifications(&self, cookie: u32) -> Result<()>;
+
+    /// Is the shell running and responding
+    fn is_connected(&self) -> bool;
+
+    /// Get the operations and events supported by the shell
+    fn get_capabilities(&self) -> Result<Capabilities>;
+}
+
+/// Backend used by the functions of this crate, select with `set_backend`
+#[derive(Clone, Default)]
+pub enum Backend {
+    /// Windows shell through COM, this is the default
+    #[default]
+    Com,
+
+    /// In-memory simulated shell
+    Simulated(Arc<SimulatedShell>),
+
+    /// Windows shell through COM, called from a dedicated thread
+    ComThread(Arc<ComThread>),
+}
+
+impl From<Arc<SimulatedShell>> for Backend {
+    fn from(shell: Arc<SimulatedShell>) -> Self {
+        Backend::Simulated(shell)
+    }
+}
+
+impl Backend {
+    /// Is the backend usable on this platform
+    pub fn is_available(&self) -> bool {
+        match self {
+            Backend::Com => cfg!(windows),
+            Backend::Simulated(_) => true,
+            Backend::ComThread(thread) => thread.is_available(),
+        }
+    }
+
+    /// Runs the function with an instance of the backend that is not shared
+    /// with other threads.
+    ///
+    /// Listener threads use this to own their COM objects. The simulated shell
+    /// is always shared.
+    pub(crate) fn with_dedicated<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&dyn VirtualDesktopBackend) -> Result<T>,
+    {
+        match self {
+            #[cfg(windows)]
+            Backend::Com => f(ComBackend::new().get()),
+
+            #[cfg(not(windows))]
+            Backend::Com => Err(Error::BackendNotAvailable),
+
+            Backend::Simulated(shell) => f(shell.as_ref()),
+
+            Backend::ComThread(thread) => thread.backend().with_dedicated(f),
+        }
+    }
+}
+
+static BACKEND: RwLock<Backend> = RwLock::new(Backend::Com);
+
+/// Select the backend used by all functions of this crate
+///
+/// Listeners created earlier keep using the backend they were created with.
+///
+/// # Example
+///
+/// ```rust
+/// let shell = std::sync::Arc::new(winvd::SimulatedShell::with_desktops(3));
+/// winvd::set_backend(winvd::Backend::Simulated(shell));
+/// winvd::switch_desktop(2).unwrap();
+/// ```
+pub fn set_backend(backend: Backend) {
+    *BACKEND.write().unwrap_or_else(|e| e.into_inner()) = backend;
+}
+
+/// Get the currently selected backend
+pub fn get_backend() -> Backend {
+    BACKEND.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// This is a helper function to run the function with the selected backend.
+///
+/// For the COM backend the objects are kept in a thread local, see
+/// `comobjects::with_thread_com_objects`, or owned by the `ComThread` the
+/// function is called on. With `Backend::ComThread` the
+/// function is run on the dedicated thread, with the retry policy of the
+/// calling thread.
+pub fn with_com_objects<F, T>(f: F) -> Result<T>
+where
+    F: Fn(&dyn VirtualDesktopBackend) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let policy = current_retry_policy();
+    let run = move |backend: &dyn VirtualDesktopBackend| Ok(run_scoped(policy, || f(backend)));
+    let (result, retries) = match get_backend() {
+        #[cfg(windows)]
+        Backend::Com => crate::executor::with_com_thread_objects(&run)
+            .unwrap_or_else(|| crate::comobjects::with_thread_com_objects(run)),
+
+        #[cfg(not(windows))]
+        Backend::Com => Err(Error::BackendNotAvailable),
+
+        Backend::Simulated(shell) => run(shell.as_ref()),
+
+        Backend::ComThread(thread) => thread.run(run),
+    }?;
+    record_retries(retries);
+    result
+}
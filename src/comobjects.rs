@@ -1,18 +1,23 @@
 /// This module contains COM object for accessing the Windows Virtual Desktop API
 use super::interfaces::*;
-use super::Result;
+use super::listener::VirtualDesktopNotification;
+use super::retry::retry;
+use super::version::{interface_table, interface_table_for, InterfaceLayout};
+use super::*;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::rc::Rc;
 use std::{cell::RefCell, ffi::c_void};
 use windows::core::ComInterface;
-use windows::core::HRESULT;
 use windows::Win32::Foundation::HWND;
 use windows::Win32::System::Com::CoIncrementMTAUsage;
 use windows::Win32::System::Com::CLSCTX_LOCAL_SERVER;
+use windows::Win32::UI::WindowsAndMessaging::{GetWindowTextLengthW, GetWindowTextW, IsWindow};
 use windows::{
-    core::{Interface, GUID, HSTRING},
+    core::{IUnknown, Interface, GUID, HSTRING},
     Win32::{System::Com::CoCreateInstance, UI::Shell::Common::IObjectArray},
 };
+use windows10::Windows10ComObjects;
 
 #[cfg(debug_assertions)]
 use crate::log::log_output;
@@ -20,48 +25,7 @@ use crate::log::log_output;
 type WCHAR = u16;
 type APPIDPWSTR = *const WCHAR;
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Error {
-    /// Window is not found
-    WindowNotFound,
-
-    /// Desktop with given ID is not found
-    DesktopNotFound,
-
-    /// Creationg of desktop failed
-    CreateDesktopFailed,
-
-    /// Remove desktop failed
-    RemoveDesktopFailed,
-
-    /// Unable to create service, ensure that explorer.exe is running
-    ClassNotRegistered,
-
-    /// Unable to connect to service
-    RpcServerNotAvailable,
-
-    /// Com is not initialized, call CoInitializeEx or CoIncrementMTAUsage
-    ComNotInitialized,
-
-    /// Com object not connected
-    ComObjectNotConnected,
-
-    /// Generic element not found
-    ComElementNotFound,
-
-    /// Some unhandled COM error
-    ComError(HRESULT),
-
-    /// This should not happen, this means that successful COM call allocated a
-    /// null pointer, in this case it is an error in the COM service, or it's
-    /// usage.
-    ComAllocatedNullPtr,
-
-    /// Borrow error
-    InternalBorrowError,
-}
-
-trait HRESULTHelpers {
+pub(crate) trait HRESULTHelpers {
     fn as_error(&self) -> Error;
     fn as_result(&self) -> Result<()>;
 }
@@ -88,7 +52,7 @@ impl HRESULTHelpers for ::windows::core::HRESULT {
             // 0x800401F0
             return Error::ComNotInitialized;
         }
-        Error::ComError(self.clone())
+        Error::ComError(ComError::new(*self))
     }
 
     fn as_result(&self) -> Result<()> {
@@ -105,41 +69,6 @@ impl From<::windows::core::Error> for Error {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub enum DesktopInternal {
-    Index(u32),
-    Guid(GUID),
-    IndexGuid(u32, GUID),
-}
-
-// Impl from u32 to DesktopTest
-impl From<u32> for DesktopInternal {
-    fn from(index: u32) -> Self {
-        DesktopInternal::Index(index)
-    }
-}
-
-// Impl from i32 to DesktopTest
-impl From<i32> for DesktopInternal {
-    fn from(index: i32) -> Self {
-        DesktopInternal::Index(index as u32)
-    }
-}
-
-// Impl from GUID to DesktopTest
-impl From<GUID> for DesktopInternal {
-    fn from(guid: GUID) -> Self {
-        DesktopInternal::Guid(guid)
-    }
-}
-
-// Impl from &GUID to DesktopTest
-impl From<&GUID> for DesktopInternal {
-    fn from(guid: &GUID) -> Self {
-        DesktopInternal::Guid(*guid)
-    }
-}
-
 impl<'a> TryFrom<&'a IVirtualDesktop> for DesktopInternal {
     type Error = Error;
 
@@ -159,6 +88,28 @@ impl<'a> TryFrom<&'a ComIn<'a, IVirtualDesktop>> for DesktopInternal {
     }
 }
 
+impl TryFrom<IVirtualDesktop> for Desktop {
+    type Error = Error;
+
+    fn try_from(desktop: IVirtualDesktop) -> Result<Self> {
+        Ok(DesktopInternal::try_from(&desktop)?.into())
+    }
+}
+impl<'a> TryFrom<&'a ComIn<'a, IVirtualDesktop>> for Desktop {
+    type Error = Error;
+
+    fn try_from(desktop: &'a ComIn<'a, IVirtualDesktop>) -> Result<Self> {
+        Ok(DesktopInternal::try_from(desktop)?.into())
+    }
+}
+impl<'a> TryFrom<ComIn<'a, IVirtualDesktop>> for Desktop {
+    type Error = Error;
+
+    fn try_from(desktop: ComIn<'a, IVirtualDesktop>) -> Result<Self> {
+        Ok(DesktopInternal::try_from(&desktop)?.into())
+    }
+}
+
 pub struct ComObjects {
     provider: RefCell<Option<Rc<IServiceProvider>>>,
     manager: RefCell<Option<Rc<IVirtualDesktopManager>>>,
@@ -166,56 +117,43 @@ pub struct ComObjects {
     notification_service: RefCell<Option<Rc<IVirtualDesktopNotificationService>>>,
     pinned_apps: RefCell<Option<Rc<IVirtualDesktopPinnedApps>>>,
     view_collection: RefCell<Option<Rc<IApplicationViewCollection>>>,
+    notifications: RefCell<HashMap<u32, IUnknown>>,
+}
+
+/// COM objects caching services, the services are dropped before retrying
+trait ComServices {
+    fn drop_services(&self);
 }
 
-fn retry_function<F, R>(com_objects: &ComObjects, f: F) -> Result<R>
+fn retry_function<S, F, R>(com_objects: &S, f: F) -> Result<R>
 where
+    S: ComServices,
     F: Fn() -> Result<R>,
 {
-    let mut value = f();
-    for _ in 0..3 {
-        match &value {
-            Err(er)
-                if er == &Error::ClassNotRegistered
-                    || er == &Error::RpcServerNotAvailable
-                    || er == &Error::ComObjectNotConnected
-                    || er == &Error::ComAllocatedNullPtr
-                    || er == &Error::ComNotInitialized =>
-            {
-                #[cfg(debug_assertions)]
-                log_output(&format!("Retry the function after {:?}", er));
-
-                if er == &Error::ComNotInitialized {
-                    let _ = unsafe { CoIncrementMTAUsage() };
-                }
-
-                drop(value);
-
-                // If private function is decorated, then this drop_services call
-                // will cause borrow issues.
-                com_objects.drop_services();
-
-                value = f();
-            }
-            _ => {
-                break;
-            }
+    let value = retry(f, |er| {
+        if er == &Error::ComNotInitialized {
+            let _ = unsafe { CoIncrementMTAUsage() };
         }
-    }
+
+        // Decorated functions called by a decorated function are not
+        // retried by themselves, so the services are not in use here
+        com_objects.drop_services();
+    });
 
     #[cfg(debug_assertions)]
     if let Err(er) = &value {
-        log_output(&format!("Com_objects function failed with {:?}", er));
+        log_output(&format!("Com_objects function failed with {}", er));
     }
 
     value
 }
 
-/// Safely reruns the function if it returns one of the recoverable errors
+/// Safely reruns the function if it returns one of the recoverable errors, as
+/// given by the current `RetryPolicy`
 ///
 /// This should be applied to only public functions in ComObjects struct, having
-/// it in private functions is not necessary. Decorating private functions will
-/// also cause borrowing issues.
+/// it in private functions is not necessary. When decorated functions call
+/// each other, only the outermost call is retried.
 macro_rules! retry_function {(
     $( #[$attr:meta] )*
     $pub:vis
@@ -231,12 +169,43 @@ macro_rules! retry_function {(
         &$self_, $( $arg_name : $ArgTy ),*
     ) -> $RetTy
     {
-        retry_function(&$self_, || -> $RetTy {
+        retry_function($self_, || -> $RetTy {
             $body
         })
+        .map_err(|er| {
+            er.with_context(
+                stringify!($fname),
+                None $( .or_else(|| $arg_name.error_target()) )*,
+            )
+        })
     }
 )}
 
+/// Arguments of the decorated functions, the first desktop or window is
+/// reported in the `ComError`
+trait ErrorTargetArg {
+    fn error_target(&self) -> Option<ErrorTarget> {
+        None
+    }
+}
+
+impl ErrorTargetArg for Desktop {
+    fn error_target(&self) -> Option<ErrorTarget> {
+        Some((*self).into())
+    }
+}
+
+impl ErrorTargetArg for Window {
+    fn error_target(&self) -> Option<ErrorTarget> {
+        Some(ErrorTarget::Window(self.hwnd()))
+    }
+}
+
+impl ErrorTargetArg for str {}
+impl ErrorTargetArg for u32 {}
+impl ErrorTargetArg for Direction {}
+impl ErrorTargetArg for IApplicationView {}
+
 impl ComObjects {
     pub fn new() -> Self {
         Self {
@@ -246,6 +215,7 @@ impl ComObjects {
             notification_service: RefCell::new(None),
             pinned_apps: RefCell::new(None),
             view_collection: RefCell::new(None),
+            notifications: RefCell::new(HashMap::new()),
         }
     }
 
@@ -255,6 +225,9 @@ impl ComObjects {
             .try_borrow_mut()
             .map_err(|_| Error::InternalBorrowError)?;
         if provider.is_none() {
+            // Interfaces of unknown builds are not called
+            interface_table()?;
+
             let new_provider = Rc::new(unsafe {
                 CoCreateInstance(&CLSID_ImmersiveShell, None, CLSCTX_LOCAL_SERVER)?
             });
@@ -305,7 +278,7 @@ impl ComObjects {
                 provider
                     .query_service(
                         &CLSID_VirtualDesktopManagerInternal,
-                        &IVirtualDesktopManagerInternal::IID,
+                        &interface_table_for(InterfaceLayout::Windows11)?.manager_internal,
                         &mut obj,
                     )
                     .as_result()?;
@@ -387,7 +360,7 @@ impl ComObjects {
                 provider
                     .query_service(
                         &IApplicationViewCollection::IID,
-                        &IApplicationViewCollection::IID,
+                        &interface_table()?.view_collection,
                         &mut obj,
                     )
                     .as_result()?;
@@ -403,56 +376,6 @@ impl ComObjects {
             .ok_or(Error::ComAllocatedNullPtr)
     }
 
-    fn drop_services(&self) {
-        // Current implementation would be safe drop like this, but in case I
-        // ever refactor I don't use this:
-
-        // drop(self.provider.take());
-        // drop(self.manager.take());
-        // drop(self.manager_internal.take());
-
-        // Instead I use try_borrow_mut() and map() to drop services.
-        let _ = self.provider.try_borrow_mut().map(|mut v| v.take());
-        let _ = self.manager.try_borrow_mut().map(|mut v| v.take());
-        let _ = self.manager_internal.try_borrow_mut().map(|mut v| v.take());
-        let _ = self
-            .notification_service
-            .try_borrow_mut()
-            .map(|mut v| v.take());
-        let _ = self.pinned_apps.try_borrow_mut().map(|mut v| v.take());
-        let _ = self.view_collection.try_borrow_mut().map(|mut v| v.take());
-    }
-
-    pub(crate) fn is_connected(&self) -> bool {
-        // TODO: What is a best way to check if service is connected?
-
-        // Calling any method yields an error if service is not connected.
-        //
-        // I call get_count method, if it's well implemented it should be just
-        // like returning a value, not allocating anything.
-        match self.get_manager_internal() {
-            Ok(manager_internal) => {
-                let mut out_count = 0;
-                let res = unsafe {
-                    manager_internal
-                        .get_desktop_count(&mut out_count)
-                        .as_result()
-                };
-
-                #[cfg(debug_assertions)]
-                if let Err(er) = &res {
-                    log_output(&format!("is connected error: {:?} {}", er, out_count));
-                }
-
-                if out_count == 0 || res.is_err() {
-                    return false;
-                }
-                return true;
-            }
-            Err(_) => false,
-        }
-    }
-
     fn get_idesktops_array(&self) -> Result<IObjectArray> {
         let mut desktops = None;
         unsafe {
@@ -472,52 +395,41 @@ impl ComObjects {
                 return Ok(i);
             }
         }
-        Err(Error::DesktopNotFound)
+        Err(Error::DesktopNotFound(ErrorTarget::DesktopId(*id)))
     }
 
     fn get_desktop_guid_by_index(&self, id: u32) -> Result<GUID> {
         let desktops = self.get_idesktops_array()?;
         let count = unsafe { desktops.GetCount()? };
         if id >= count {
-            return Err(Error::DesktopNotFound);
+            return Err(Error::DesktopNotFound(ErrorTarget::DesktopIndex(id)));
         }
         get_idesktop_guid(&unsafe { desktops.GetAt(id)? })
     }
 
-    fn get_idesktop(&self, desktop: &DesktopInternal) -> Result<IVirtualDesktop> {
-        match desktop {
+    fn get_idesktop(&self, desktop: &Desktop) -> Result<IVirtualDesktop> {
+        match DesktopInternal::from(*desktop) {
             DesktopInternal::Index(id) => {
                 let desktops = self.get_idesktops_array()?;
                 let count = unsafe { desktops.GetCount()? };
-                if *id >= count {
-                    return Err(Error::DesktopNotFound);
-                }
-                Ok(unsafe { desktops.GetAt(*id)? })
-            }
-            DesktopInternal::Guid(id) => {
-                let manager = self.get_manager_internal()?;
-                let mut desktop = None;
-                unsafe {
-                    manager.find_desktop(id, &mut desktop).as_result()?;
+                if id >= count {
+                    return Err(Error::DesktopNotFound(ErrorTarget::DesktopIndex(id)));
                 }
-                desktop.ok_or(Error::DesktopNotFound)
+                Ok(unsafe { desktops.GetAt(id)? })
             }
-            DesktopInternal::IndexGuid(_, id) => {
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => {
                 let manager = self.get_manager_internal()?;
                 let mut desktop = None;
                 unsafe {
-                    manager.find_desktop(id, &mut desktop).as_result()?;
+                    manager.find_desktop(&id, &mut desktop).as_result()?;
                 }
-                desktop.ok_or(Error::DesktopNotFound)
+                desktop.ok_or(Error::DesktopNotFound(ErrorTarget::DesktopId(id)))
             }
         }
     }
 
-    fn move_view_to_desktop(
-        &self,
-        view: ComIn<IApplicationView>,
-        desktop: &DesktopInternal,
-    ) -> Result<()> {
+    fn move_view_to_desktop(&self, view: ComIn<IApplicationView>, desktop: &Desktop) -> Result<()> {
+        let target = ErrorTarget::from(*desktop);
         let desktop = self.get_idesktop(desktop)?;
         unsafe {
             self.get_manager_internal()?
@@ -525,7 +437,7 @@ impl ComObjects {
                 .as_result()
                 .map_err(|e| {
                     if e == Error::ComElementNotFound {
-                        Error::DesktopNotFound
+                        Error::DesktopNotFound(target)
                     } else {
                         e
                     }
@@ -538,75 +450,251 @@ impl ComObjects {
         let mut view = None;
         unsafe {
             self.get_view_collection()?
-                .get_view_for_hwnd(hwnd.clone(), &mut view)
+                .get_view_for_hwnd(*hwnd, &mut view)
                 .as_result()
                 .map_err(|er| {
                     if er == Error::ComElementNotFound {
-                        Error::WindowNotFound
+                        Error::WindowNotFound(*hwnd)
                     } else {
                         er
                     }
                 })?
         }
-        view.ok_or(Error::WindowNotFound)
+        view.ok_or(Error::WindowNotFound(*hwnd))
     }
 
     #[apply(retry_function)]
-    pub fn get_desktop_index(&self, id: &DesktopInternal) -> Result<u32> {
-        match id {
-            DesktopInternal::Index(id) => Ok(*id),
-            DesktopInternal::Guid(guid) => self.get_desktop_index_by_guid(guid),
-            DesktopInternal::IndexGuid(id, _) => Ok(*id),
+    fn get_iapplication_id_for_view(&self, view: &IApplicationView) -> Result<APPIDPWSTR> {
+        let mut app_id: APPIDPWSTR = std::ptr::null_mut();
+        unsafe {
+            view.get_app_user_model_id(&mut app_id as *mut _ as *mut _)
+                .as_result()?
         }
+        Ok(app_id)
     }
 
-    #[apply(retry_function)]
-    pub fn get_desktop_id(&self, desktop: &DesktopInternal) -> Result<GUID> {
-        match desktop {
-            DesktopInternal::Index(id) => self.get_desktop_guid_by_index(*id),
-            DesktopInternal::Guid(guid) => Ok(*guid),
-            DesktopInternal::IndexGuid(_, guid) => Ok(*guid),
+    fn get_app_id_of_view(&self, view: &IApplicationView) -> Result<String> {
+        let app_id = self.get_iapplication_id_for_view(view)?;
+        if app_id.is_null() {
+            return Err(Error::ComAllocatedNullPtr);
         }
+        Ok(unsafe { windows::core::PCWSTR::from_raw(app_id).to_string() }.unwrap_or_default())
     }
 
-    #[apply(retry_function)]
-    pub fn get_desktops(&self) -> Result<Vec<DesktopInternal>> {
-        let desktops = self.get_idesktops_array()?;
-        let count = unsafe { desktops.GetCount()? };
+    fn get_window_of_view(&self, view: &IApplicationView) -> Result<Option<DesktopWindow>> {
+        let pinned_apps = self.get_pinned_apps()?;
+        let mut hwnd = HWND::default();
+        let mut desktop = GUID::default();
+        let mut is_view_pinned = false;
+        let mut is_app_pinned = false;
+        unsafe {
+            view.get_thumbnail_window(&mut hwnd).as_result()?;
+            view.get_virtual_desktop_id(&mut desktop).as_result()?;
+            pinned_apps
+                .is_view_pinned(ComIn::new(view), &mut is_view_pinned)
+                .as_result()?;
+            if let Ok(app_id) = self.get_iapplication_id_for_view(view) {
+                pinned_apps
+                    .is_app_pinned(app_id, &mut is_app_pinned)
+                    .as_result()?;
+            }
+        }
+        let is_pinned = is_view_pinned || is_app_pinned;
+
+        // Views without a desktop are not shown on any desktop
+        if !is_pinned && desktop == GUID::default() {
+            return Ok(None);
+        }
+        Ok(Some(DesktopWindow {
+            window: hwnd.into(),
+            desktop: (!is_pinned).then(|| desktop.into()),
+            is_pinned,
+        }))
+    }
+
+    fn get_windows_of_views(&self, views: &IObjectArray) -> Result<Vec<DesktopWindow>> {
+        let count = unsafe { views.GetCount()? };
         let mut result = Vec::with_capacity(count as usize);
         for i in 0..count {
-            let desktop = unsafe { desktops.GetAt(i)? };
-            let id = get_idesktop_guid(&desktop)?;
-            result.push(DesktopInternal::IndexGuid(i, id));
+            let view: IApplicationView = unsafe { views.GetAt(i)? };
+            if let Some(window) = self.get_window_of_view(&view)? {
+                result.push(window);
+            }
         }
         Ok(result)
     }
 
-    #[apply(retry_function)]
-    pub fn register_for_notifications(
+    /// Window on the desktop, given by GUID, that was active last
+    fn get_last_active_window_of(
         &self,
-        // notification: &IVirtualDesktopNotification,
-        notification: *mut c_void, // IVirtualDesktopNotification raw pointer
-    ) -> Result<u32> {
+        desktop: Desktop,
+        is_current: bool,
+    ) -> Result<Option<DesktopWindow>> {
+        let desktop = Some(desktop);
+
+        // Shell keeps track of the last active view on the current desktop
+        if is_current {
+            let mut view = None;
+            let result = unsafe {
+                self.get_view_collection()?
+                    .try_get_last_active_visible_view(&mut view)
+                    .as_result()
+            };
+            if let (Ok(()), Some(view)) = (result, view) {
+                match self.get_window_of_view(&view)? {
+                    Some(window) if window.desktop == desktop => return Ok(Some(window)),
+                    _ => {}
+                }
+            }
+        }
+
+        // Otherwise the view on the desktop that was activated last
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views(&mut views)
+                .as_result()?
+        }
+        let views = views.ok_or(Error::ComAllocatedNullPtr)?;
+        let count = unsafe { views.GetCount()? };
+        let mut last: Option<(u64, DesktopWindow)> = None;
+        for i in 0..count {
+            let view: IApplicationView = unsafe { views.GetAt(i)? };
+            let window = match self.get_window_of_view(&view)? {
+                Some(window) if window.desktop == desktop => window,
+                _ => continue,
+            };
+            let mut show_in_switchers = 0;
+            let mut timestamp = 0;
+            unsafe {
+                view.get_show_in_switchers(&mut show_in_switchers)
+                    .as_result()?;
+                view.get_last_activation_timestamp(&mut timestamp)
+                    .as_result()?;
+            }
+            let is_newer = !matches!(last, Some((t, _)) if t >= timestamp);
+            if show_in_switchers != 0 && is_newer {
+                last = Some((timestamp, window));
+            }
+        }
+        Ok(last.map(|(_, window)| window))
+    }
+
+    /// Registers the notification object as the notification interface of the
+    /// running build, returns the cookie and the registered interface
+    fn register_notification(&self, notification: IUnknown) -> Result<(u32, IUnknown)> {
         let notification_service = self.get_notification_service()?;
 
         unsafe {
+            let mut obj = std::ptr::null_mut::<c_void>();
+            notification
+                .query(&interface_table()?.notification, &mut obj)
+                .as_result()?;
+            assert!(!obj.is_null());
+            let notification = IUnknown::from_raw(obj);
+
             let mut cookie = 0;
             notification_service
-                .register(notification, &mut cookie)
+                .register(notification.as_raw(), &mut cookie)
                 .as_result()
-                .map(|_| cookie)
+                .map(|_| (cookie, notification))
         }
     }
 
     #[apply(retry_function)]
-    pub fn unregister_for_notifications(&self, cookie: u32) -> Result<()> {
+    fn unregister_notification(&self, cookie: u32) -> Result<()> {
         let notification_service = self.get_notification_service()?;
         unsafe { notification_service.unregister(cookie).as_result() }
     }
+}
+
+impl ComServices for ComObjects {
+    fn drop_services(&self) {
+        // Current implementation would be safe drop like this, but in case I
+        // ever refactor I don't use this:
+
+        // drop(self.provider.take());
+        // drop(self.manager.take());
+        // drop(self.manager_internal.take());
+
+        // Instead I use try_borrow_mut() and map() to drop services.
+        let _ = self.provider.try_borrow_mut().map(|mut v| v.take());
+        let _ = self.manager.try_borrow_mut().map(|mut v| v.take());
+        let _ = self.manager_internal.try_borrow_mut().map(|mut v| v.take());
+        let _ = self
+            .notification_service
+            .try_borrow_mut()
+            .map(|mut v| v.take());
+        let _ = self.pinned_apps.try_borrow_mut().map(|mut v| v.take());
+        let _ = self.view_collection.try_borrow_mut().map(|mut v| v.take());
+    }
+}
+
+impl VirtualDesktopBackend for ComObjects {
+    #[apply(retry_function)]
+    fn get_desktop_index(&self, desktop: &Desktop) -> Result<u32> {
+        match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(id) => Ok(id),
+            DesktopInternal::Guid(guid) => self.get_desktop_index_by_guid(&guid),
+            DesktopInternal::IndexGuid(id, _) => Ok(id),
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_desktop_id(&self, desktop: &Desktop) -> Result<GUID> {
+        match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(id) => self.get_desktop_guid_by_index(id),
+            DesktopInternal::Guid(guid) => Ok(guid),
+            DesktopInternal::IndexGuid(_, guid) => Ok(guid),
+        }
+    }
 
     #[apply(retry_function)]
-    pub fn switch_desktop(&self, desktop: &DesktopInternal) -> Result<()> {
+    fn get_desktops(&self) -> Result<Vec<Desktop>> {
+        let desktops = self.get_idesktops_array()?;
+        let count = unsafe { desktops.GetCount()? };
+        let mut result = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let desktop = unsafe { desktops.GetAt(i)? };
+            let id = get_idesktop_guid(&desktop)?;
+            result.push(DesktopInternal::IndexGuid(i, id).into());
+        }
+        Ok(result)
+    }
+
+    fn register_for_notifications(
+        &self,
+        sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+        mask: EventMask,
+    ) -> Result<u32> {
+        // Views are tracked only for the window events
+        let windows = match mask.intersects(EventMask::WINDOWS) {
+            true => self.get_all_windows().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let (cookie, notification) = self
+            .register_notification(VirtualDesktopNotification::new(sender, mask, windows).into())?;
+        self.notifications
+            .try_borrow_mut()
+            .map_err(|_| Error::InternalBorrowError)?
+            .insert(cookie, notification);
+        Ok(cookie)
+    }
+
+    fn unregister_for_notifications(&self, cookie: u32) -> Result<()> {
+        // The notification object is kept alive until it's unregistered
+        let notification = self
+            .notifications
+            .try_borrow_mut()
+            .map_err(|_| Error::InternalBorrowError)?
+            .remove(&cookie);
+        let result = self.unregister_notification(cookie);
+        drop(notification);
+        result
+    }
+
+    #[apply(retry_function)]
+    fn switch_desktop(&self, desktop: &Desktop) -> Result<()> {
         let desktop = self.get_idesktop(desktop)?;
         unsafe {
             self.get_manager_internal()?
@@ -617,7 +705,7 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn create_desktop(&self) -> Result<DesktopInternal> {
+    fn create_desktop(&self) -> Result<Desktop> {
         let mut desktop = None;
         unsafe {
             self.get_manager_internal()?
@@ -627,15 +715,11 @@ impl ComObjects {
         let desktop = desktop.ok_or(Error::ComAllocatedNullPtr)?;
         let id = get_idesktop_guid(&desktop)?;
         let index = self.get_desktop_index_by_guid(&id)?;
-        Ok(DesktopInternal::IndexGuid(index, id))
+        Ok(DesktopInternal::IndexGuid(index, id).into())
     }
 
     #[apply(retry_function)]
-    pub fn remove_desktop(
-        &self,
-        desktop: &DesktopInternal,
-        fallback_desktop: &DesktopInternal,
-    ) -> Result<()> {
+    fn remove_desktop(&self, desktop: &Desktop, fallback_desktop: &Desktop) -> Result<()> {
         let desktop = self.get_idesktop(desktop)?;
         let fb_desktop = self.get_idesktop(fallback_desktop)?;
         unsafe {
@@ -647,21 +731,60 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn is_window_on_desktop(&self, window: &HWND, desktop: &DesktopInternal) -> Result<bool> {
+    fn move_desktop(&self, desktop: &Desktop, index: u32) -> Result<()> {
+        if index >= self.get_desktop_count()? {
+            return Err(Error::InvalidDesktopIndex(index));
+        }
+        let desktop = self.get_idesktop(desktop)?;
+        unsafe {
+            self.get_manager_internal()?
+                .move_desktop(ComIn::new(&desktop), index)
+                .as_result()
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_adjacent_desktop(
+        &self,
+        desktop: &Desktop,
+        direction: Direction,
+    ) -> Result<Option<Desktop>> {
+        let desktop = self.get_idesktop(desktop)?;
+        let direction = match direction {
+            Direction::Left => 3,
+            Direction::Right => 4,
+        };
+        let mut adjacent = None;
+        let result = unsafe {
+            self.get_manager_internal()?
+                .get_adjacent_desktop(ComIn::new(&desktop), direction, &mut adjacent)
+                .as_result()
+        };
+        match result {
+            // There is no desktop in that direction
+            Err(Error::ComElementNotFound) => return Ok(None),
+            result => result?,
+        }
+        let adjacent = adjacent.ok_or(Error::ComAllocatedNullPtr)?;
+        Ok(Some(adjacent.try_into()?))
+    }
+
+    #[apply(retry_function)]
+    fn is_window_on_desktop(&self, window: &Window, desktop: &Desktop) -> Result<bool> {
         let desktop_win = self.get_desktop_by_window(window)?;
-        Ok(self.get_desktop_id(&desktop_win)? == self.get_desktop_id(&*desktop)?)
+        Ok(self.get_desktop_id(&desktop_win)? == self.get_desktop_id(desktop)?)
     }
 
     #[apply(retry_function)]
-    pub fn is_window_on_current_desktop(&self, window: &HWND) -> Result<bool> {
+    fn is_window_on_current_desktop(&self, window: &Window) -> Result<bool> {
         unsafe {
             let mut value = false;
             self.get_manager()?
-                .is_window_on_current_desktop(window.clone(), &mut value)
+                .is_window_on_current_desktop(window.hwnd(), &mut value)
                 .as_result()
                 .map_err(|er| match er {
                     // Window does not exist
-                    Error::ComElementNotFound => Error::WindowNotFound,
+                    Error::ComElementNotFound => Error::WindowNotFound(window.hwnd()),
                     _ => er,
                 })?;
             Ok(value)
@@ -669,13 +792,28 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn move_window_to_desktop(&self, window: &HWND, desktop: &DesktopInternal) -> Result<()> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn can_move_window(&self, window: &Window) -> Result<bool> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
+        let mut can_move = 0;
+        unsafe {
+            self.get_manager_internal()?
+                .can_move_view_between_desktops(ComIn::new(&view), &mut can_move)
+                .as_result()?
+        }
+        Ok(can_move != 0)
+    }
+
+    #[apply(retry_function)]
+    fn move_window_to_desktop(&self, window: &Window, desktop: &Desktop) -> Result<()> {
+        if !self.can_move_window(window)? {
+            return Err(Error::WindowNotMovable(window.hwnd()));
+        }
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         self.move_view_to_desktop(ComIn::new(&view), desktop)
     }
 
     #[apply(retry_function)]
-    pub fn get_desktop_count(&self) -> Result<u32> {
+    fn get_desktop_count(&self) -> Result<u32> {
         let manager = self.get_manager_internal()?;
         let mut count = 0;
         unsafe {
@@ -685,26 +823,26 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn get_desktop_by_window(&self, window: &HWND) -> Result<DesktopInternal> {
+    fn get_desktop_by_window(&self, window: &Window) -> Result<Desktop> {
         let mut desktop = GUID::default();
         unsafe {
             self.get_manager()?
-                .get_desktop_by_window(window.clone(), &mut desktop)
+                .get_desktop_by_window(window.hwnd(), &mut desktop)
                 .as_result()
                 .map_err(|er| match er {
                     // Window does not exist
-                    Error::ComElementNotFound => Error::WindowNotFound,
+                    Error::ComElementNotFound => Error::WindowNotFound(window.hwnd()),
                     _ => er,
                 })?
         };
         if desktop == GUID::default() {
-            return Err(Error::WindowNotFound);
+            return Err(Error::WindowNotFound(window.hwnd()));
         }
-        Ok(DesktopInternal::Guid(desktop))
+        Ok(desktop.into())
     }
 
     #[apply(retry_function)]
-    pub fn get_current_desktop(&self) -> Result<DesktopInternal> {
+    fn get_current_desktop(&self) -> Result<Desktop> {
         let mut desktop = None;
         unsafe {
             self.get_manager_internal()?
@@ -713,12 +851,124 @@ impl ComObjects {
         }
         let desktop = desktop.ok_or(Error::ComAllocatedNullPtr)?;
         let id = get_idesktop_guid(&desktop)?;
-        Ok(DesktopInternal::Guid(id))
+        Ok(id.into())
+    }
+
+    #[apply(retry_function)]
+    fn get_all_windows(&self) -> Result<Vec<DesktopWindow>> {
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views(&mut views)
+                .as_result()?
+        }
+        self.get_windows_of_views(&views.ok_or(Error::ComAllocatedNullPtr)?)
+    }
+
+    #[apply(retry_function)]
+    fn get_all_windows_by_zorder(&self) -> Result<Vec<DesktopWindow>> {
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views_by_zorder(&mut views)
+                .as_result()?
+        }
+        self.get_windows_of_views(&views.ok_or(Error::ComAllocatedNullPtr)?)
+    }
+
+    #[apply(retry_function)]
+    fn get_focused_window(&self) -> Result<Option<DesktopWindow>> {
+        let mut view = None;
+        let result = unsafe {
+            self.get_view_collection()?
+                .get_view_in_focus(&mut view)
+                .as_result()
+        };
+        match result {
+            // Nothing is focused
+            Err(Error::ComElementNotFound) => return Ok(None),
+            result => result?,
+        }
+        match view {
+            Some(view) => self.get_window_of_view(&view),
+            None => Ok(None),
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_last_active_window(&self, desktop: &Desktop) -> Result<Option<DesktopWindow>> {
+        let desktop: Desktop = self.get_desktop_id(desktop)?.into();
+        let is_current = desktop == self.get_current_desktop()?;
+        self.get_last_active_window_of(desktop, is_current)
+    }
+
+    fn get_window_title(&self, window: &Window) -> Result<String> {
+        unsafe {
+            if !IsWindow(window.hwnd()).as_bool() {
+                return Err(Error::WindowNotFound(window.hwnd()));
+            }
+            let mut title = vec![0u16; GetWindowTextLengthW(window.hwnd()) as usize + 1];
+            let len = GetWindowTextW(window.hwnd(), &mut title);
+            Ok(String::from_utf16_lossy(&title[..len as usize]))
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_window_info(&self, window: &Window) -> Result<WindowInfo> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
+        let mut info = WindowInfo {
+            window: *window,
+            app_id: self.get_app_id_of_view(&view)?,
+            ..Default::default()
+        };
+        let mut view_state = 0;
+        let mut visibility = 0i32;
+        let mut show_in_switchers = 0;
+        let mut can_receive_input = 0;
+        let mut is_splash_screen_presented = 0;
+        unsafe {
+            view.get_view_state(&mut view_state).as_result()?;
+            view.get_last_activation_timestamp(&mut info.last_activation_timestamp)
+                .as_result()?;
+            view.get_show_in_switchers(&mut show_in_switchers)
+                .as_result()?;
+            view.get_extended_frame_position(&mut info.frame_rect)
+                .as_result()?;
+            view.get_scale_factor(&mut info.scale_factor).as_result()?;
+            view.get_visibility(&mut visibility as *mut i32 as *mut c_void)
+                .as_result()?;
+            view.can_receive_input(&mut can_receive_input).as_result()?;
+            view.is_splash_screen_presented(&mut is_splash_screen_presented)
+                .as_result()?;
+        }
+        info.view_state = view_state.into();
+        info.visibility = visibility.into();
+        info.show_in_switchers = show_in_switchers != 0;
+        info.can_receive_input = can_receive_input != 0;
+        info.is_splash_screen_presented = is_splash_screen_presented != 0;
+        Ok(info)
+    }
+
+    #[apply(retry_function)]
+    fn activate_window(&self, window: &Window) -> Result<()> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
+
+        // Pinned windows are on every desktop, stay on the current one
+        if !self.is_pinned_window(window)? && !self.is_pinned_app(window)? {
+            let desktop = self.get_desktop_by_window(window)?;
+            self.switch_desktop(&desktop)?;
+        }
+
+        // Unlike SetForegroundWindow these are not blocked by foreground locks
+        unsafe {
+            view.switch_to().as_result()?;
+            view.set_focus().as_result()
+        }
     }
 
     #[apply(retry_function)]
-    pub fn is_pinned_window(&self, window: &HWND) -> Result<bool> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn is_pinned_window(&self, window: &Window) -> Result<bool> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         unsafe {
             let mut value = false;
             self.get_pinned_apps()?
@@ -729,8 +979,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn pin_window(&self, window: &HWND) -> Result<()> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn pin_window(&self, window: &Window) -> Result<()> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         unsafe {
             self.get_pinned_apps()?
                 .pin_view(ComIn::new(&view))
@@ -740,8 +990,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn unpin_window(&self, window: &HWND) -> Result<()> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn unpin_window(&self, window: &Window) -> Result<()> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         unsafe {
             self.get_pinned_apps()?
                 .unpin_view(ComIn::new(&view))
@@ -751,18 +1001,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    fn get_iapplication_id_for_view(&self, view: &IApplicationView) -> Result<APPIDPWSTR> {
-        let mut app_id: APPIDPWSTR = std::ptr::null_mut();
-        unsafe {
-            view.get_app_user_model_id(&mut app_id as *mut _ as *mut _)
-                .as_result()?
-        }
-        Ok(app_id)
-    }
-
-    #[apply(retry_function)]
-    pub fn is_pinned_app(&self, window: &HWND) -> Result<bool> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn is_pinned_app(&self, window: &Window) -> Result<bool> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         let app_id = self.get_iapplication_id_for_view(&view)?;
         unsafe {
             let mut value = false;
@@ -774,8 +1014,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn pin_app(&self, window: &HWND) -> Result<()> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn pin_app(&self, window: &Window) -> Result<()> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         let app_id = self.get_iapplication_id_for_view(&view)?;
         unsafe {
             self.get_pinned_apps()?.pin_app(app_id).as_result()?;
@@ -784,8 +1024,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn unpin_app(&self, window: &HWND) -> Result<()> {
-        let view = self.get_iapplication_view_for_hwnd(window)?;
+    fn unpin_app(&self, window: &Window) -> Result<()> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
         let app_id = self.get_iapplication_id_for_view(&view)?;
         unsafe {
             self.get_pinned_apps()?.unpin_app(app_id).as_result()?;
@@ -794,8 +1034,54 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn get_desktop_name(&self, desktop: &DesktopInternal) -> Result<String> {
-        let desktop = self.get_idesktop(&desktop)?;
+    fn get_app_id(&self, window: &Window) -> Result<String> {
+        let view = self.get_iapplication_view_for_hwnd(&window.hwnd())?;
+        self.get_app_id_of_view(&view)
+    }
+
+    #[apply(retry_function)]
+    fn is_app_id_pinned(&self, app_id: &str) -> Result<bool> {
+        let app_id = to_wide(app_id);
+        let mut value = false;
+        unsafe {
+            self.get_pinned_apps()?
+                .is_app_pinned(app_id.as_ptr(), &mut value)
+                .as_result()?;
+        }
+        Ok(value)
+    }
+
+    #[apply(retry_function)]
+    fn pin_app_id(&self, app_id: &str) -> Result<()> {
+        let app_id = to_wide(app_id);
+        unsafe { self.get_pinned_apps()?.pin_app(app_id.as_ptr()).as_result() }
+    }
+
+    #[apply(retry_function)]
+    fn unpin_app_id(&self, app_id: &str) -> Result<()> {
+        let app_id = to_wide(app_id);
+        unsafe {
+            self.get_pinned_apps()?
+                .unpin_app(app_id.as_ptr())
+                .as_result()
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_windows_by_app_id(&self, app_id: &str) -> Result<Vec<DesktopWindow>> {
+        let app_id = to_wide(app_id);
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views_by_app_user_model_id(app_id.as_ptr(), &mut views)
+                .as_result()?
+        }
+        self.get_windows_of_views(&views.ok_or(Error::ComAllocatedNullPtr)?)
+    }
+
+    #[apply(retry_function)]
+    fn get_desktop_name(&self, desktop: &Desktop) -> Result<String> {
+        let desktop = self.get_idesktop(desktop)?;
         let mut name = HSTRING::default();
         unsafe {
             desktop.get_name(&mut name).as_result()?;
@@ -804,8 +1090,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn set_desktop_name(&self, desktop: &DesktopInternal, name: &str) -> Result<()> {
-        let desktop = self.get_idesktop(&desktop)?;
+    fn set_desktop_name(&self, desktop: &Desktop, name: &str) -> Result<()> {
+        let desktop = self.get_idesktop(desktop)?;
         let manager_internal = self.get_manager_internal()?;
 
         unsafe {
@@ -816,8 +1102,8 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn get_desktop_wallpaper(&self, desktop: &DesktopInternal) -> Result<String> {
-        let desktop = self.get_idesktop(&desktop)?;
+    fn get_desktop_wallpaper(&self, desktop: &Desktop) -> Result<String> {
+        let desktop = self.get_idesktop(desktop)?;
         let mut path = HSTRING::default();
         unsafe {
             desktop.get_wallpaper(&mut path).as_result()?;
@@ -826,15 +1112,63 @@ impl ComObjects {
     }
 
     #[apply(retry_function)]
-    pub fn set_desktop_wallpaper(&self, desktop: &DesktopInternal, path: &str) -> Result<()> {
+    fn set_desktop_wallpaper(&self, desktop: &Desktop, path: &str) -> Result<()> {
         let manager_internal = self.get_manager_internal()?;
-        let desktop = self.get_idesktop(&desktop)?;
+        let desktop = self.get_idesktop(desktop)?;
         unsafe {
             manager_internal
                 .set_wallpaper(ComIn::new(&desktop), HSTRING::from(path))
                 .as_result()
         }
     }
+
+    #[apply(retry_function)]
+    fn set_wallpaper_for_all(&self, path: &str) -> Result<()> {
+        unsafe {
+            self.get_manager_internal()?
+                .update_wallpaper_for_all(HSTRING::from(path))
+                .as_result()
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        // TODO: What is a best way to check if service is connected?
+
+        // Calling any method yields an error if service is not connected.
+        //
+        // I call get_count method, if it's well implemented it should be just
+        // like returning a value, not allocating anything.
+        match self.get_manager_internal() {
+            Ok(manager_internal) => {
+                let mut out_count = 0;
+                let res = unsafe {
+                    manager_internal
+                        .get_desktop_count(&mut out_count)
+                        .as_result()
+                };
+
+                #[cfg(debug_assertions)]
+                if let Err(er) = &res {
+                    log_output(&format!("is connected error: {:?} {}", er, out_count));
+                }
+
+                if out_count == 0 || res.is_err() {
+                    return false;
+                }
+                return true;
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn get_capabilities(&self) -> Result<Capabilities> {
+        Ok(Capabilities::ALL)
+    }
+}
+
+/// Null terminated UTF-16 string for PCWSTR arguments
+fn to_wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(Some(0)).collect()
 }
 
 fn get_idesktop_guid(desktop: &IVirtualDesktop) -> Result<GUID> {
@@ -843,8 +1177,32 @@ fn get_idesktop_guid(desktop: &IVirtualDesktop) -> Result<GUID> {
     Ok(guid)
 }
 
+/// COM objects for the interfaces of the running Windows build
+pub(crate) enum ComBackend {
+    Windows11(ComObjects),
+    Windows10(Windows10ComObjects),
+}
+
+impl ComBackend {
+    pub fn new() -> Self {
+        match interface_table().map(|table| table.layout) {
+            Ok(InterfaceLayout::Windows10) => ComBackend::Windows10(Windows10ComObjects::new()),
+
+            // Unknown builds fail on the first call with `UnsupportedBuild`
+            _ => ComBackend::Windows11(ComObjects::new()),
+        }
+    }
+
+    pub fn get(&self) -> &dyn VirtualDesktopBackend {
+        match self {
+            ComBackend::Windows11(objects) => objects,
+            ComBackend::Windows10(objects) => objects,
+        }
+    }
+}
+
 thread_local! {
-    static COM_OBJECTS: ComObjects = ComObjects::new();
+    static COM_OBJECTS: ComBackend = ComBackend::new();
 }
 
 /// This is a helper function to initialize and run COM related functions in a
@@ -853,16 +1211,11 @@ thread_local! {
 /// Virtual Desktop COM Objects don't like to being called from different
 /// threads rapidly, something goes wrong. This function ensures that all COM
 /// calls are done in a single thread.
-pub fn with_com_objects<F, T>(f: F) -> Result<T>
+pub(crate) fn with_thread_com_objects<F, T>(f: F) -> Result<T>
 where
-    F: Fn(&ComObjects) -> Result<T> + 'static,
-    T: 'static,
+    F: Fn(&dyn VirtualDesktopBackend) -> Result<T>,
 {
-    // return std::thread::scope(|env| {
-    //     let com2 = ComObjects::new();
-    //     run_function_and_retry(&f, &com2)
-    // });
-
-    // return COM_OBJECTS.with(|c| run_function_and_retry(&f, &c));
-    return COM_OBJECTS.with(|c| f(&c));
+    COM_OBJECTS.with(|objects| f(objects.get()))
 }
+
+mod windows10;
@@ -0,0 +1,510 @@
+/// COM objects for the Windows 10 interfaces, see `interfaces/windows10.rs`
+///
+/// Windows, views and pins use the interfaces shared with Windows 11 through
+/// the inner `ComObjects`, only the desktops and the notification differ.
+/// Desktops can not be moved and have no wallpapers on Windows 10, those
+/// operations fail with `Error::NotSupported`.
+use super::*;
+use crate::interfaces::windows10::{
+    IVirtualDesktop, IVirtualDesktop2, IVirtualDesktopManagerInternal,
+    IVirtualDesktopManagerInternal2,
+};
+use windows::Win32::Foundation::E_NOINTERFACE;
+
+impl<'a> TryFrom<ComIn<'a, IVirtualDesktop>> for Desktop {
+    type Error = Error;
+
+    fn try_from(desktop: ComIn<'a, IVirtualDesktop>) -> Result<Self> {
+        Ok(get_idesktop_guid(&desktop)?.into())
+    }
+}
+
+pub struct Windows10ComObjects {
+    shared: ComObjects,
+    manager_internal: RefCell<Option<Rc<IVirtualDesktopManagerInternal>>>,
+    manager_internal2: RefCell<Option<Rc<IVirtualDesktopManagerInternal2>>>,
+    notifications: RefCell<HashMap<u32, IUnknown>>,
+}
+
+impl ComServices for Windows10ComObjects {
+    fn drop_services(&self) {
+        let _ = self.manager_internal.try_borrow_mut().map(|mut v| v.take());
+        let _ = self
+            .manager_internal2
+            .try_borrow_mut()
+            .map(|mut v| v.take());
+        self.shared.drop_services();
+    }
+}
+
+impl Windows10ComObjects {
+    pub fn new() -> Self {
+        Self {
+            shared: ComObjects::new(),
+            manager_internal: RefCell::new(None),
+            manager_internal2: RefCell::new(None),
+            notifications: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn get_manager_internal(&self) -> Result<Rc<IVirtualDesktopManagerInternal>> {
+        let mut manager_internal = self
+            .manager_internal
+            .try_borrow_mut()
+            .map_err(|_| Error::InternalBorrowError)?;
+        if manager_internal.is_none() {
+            let mut obj = std::ptr::null_mut::<c_void>();
+            let provider = self.shared.get_provider()?;
+            unsafe {
+                provider
+                    .query_service(
+                        &CLSID_VirtualDesktopManagerInternal,
+                        &interface_table_for(InterfaceLayout::Windows10)?.manager_internal,
+                        &mut obj,
+                    )
+                    .as_result()?;
+            }
+            assert!(!obj.is_null());
+            *manager_internal = Some(Rc::new(unsafe {
+                IVirtualDesktopManagerInternal::from_raw(obj)
+            }));
+        }
+        manager_internal
+            .as_ref()
+            .map(Rc::clone)
+            .ok_or(Error::ComAllocatedNullPtr)
+    }
+
+    /// Manager for renaming desktops, fails with `NotSupported` if the build
+    /// has no desktop names
+    fn get_manager_internal2(&self) -> Result<Rc<IVirtualDesktopManagerInternal2>> {
+        let mut manager_internal2 = self
+            .manager_internal2
+            .try_borrow_mut()
+            .map_err(|_| Error::InternalBorrowError)?;
+        if manager_internal2.is_none() {
+            let mut obj = std::ptr::null_mut::<c_void>();
+            let provider = self.shared.get_provider()?;
+            unsafe {
+                provider
+                    .query_service(
+                        &CLSID_VirtualDesktopManagerInternal,
+                        &IVirtualDesktopManagerInternal2::IID,
+                        &mut obj,
+                    )
+                    .as_result()
+                    .map_err(not_supported)?;
+            }
+            assert!(!obj.is_null());
+            *manager_internal2 = Some(Rc::new(unsafe {
+                IVirtualDesktopManagerInternal2::from_raw(obj)
+            }));
+        }
+        manager_internal2
+            .as_ref()
+            .map(Rc::clone)
+            .ok_or(Error::ComAllocatedNullPtr)
+    }
+
+    fn get_idesktops_array(&self) -> Result<IObjectArray> {
+        let mut desktops = None;
+        unsafe {
+            self.get_manager_internal()?
+                .get_desktops(&mut desktops)
+                .as_result()?
+        }
+        desktops.ok_or(Error::ComAllocatedNullPtr)
+    }
+
+    fn get_desktop_index_by_guid(&self, id: &GUID) -> Result<u32> {
+        let desktops = self.get_idesktops_array()?;
+        let count = unsafe { desktops.GetCount()? };
+        for i in 0..count {
+            let desktop_id = get_idesktop_guid(&unsafe { desktops.GetAt(i)? })?;
+            if desktop_id == *id {
+                return Ok(i);
+            }
+        }
+        Err(Error::DesktopNotFound(ErrorTarget::DesktopId(*id)))
+    }
+
+    fn get_idesktop(&self, desktop: &Desktop) -> Result<IVirtualDesktop> {
+        match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(id) => {
+                let desktops = self.get_idesktops_array()?;
+                let count = unsafe { desktops.GetCount()? };
+                if id >= count {
+                    return Err(Error::DesktopNotFound(ErrorTarget::DesktopIndex(id)));
+                }
+                Ok(unsafe { desktops.GetAt(id)? })
+            }
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => {
+                let mut desktop = None;
+                unsafe {
+                    self.get_manager_internal()?
+                        .find_desktop(&id, &mut desktop)
+                        .as_result()?;
+                }
+                desktop.ok_or(Error::DesktopNotFound(ErrorTarget::DesktopId(id)))
+            }
+        }
+    }
+}
+
+impl VirtualDesktopBackend for Windows10ComObjects {
+    #[apply(retry_function)]
+    fn get_desktop_index(&self, desktop: &Desktop) -> Result<u32> {
+        match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(id) => Ok(id),
+            DesktopInternal::Guid(guid) => self.get_desktop_index_by_guid(&guid),
+            DesktopInternal::IndexGuid(id, _) => Ok(id),
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_desktop_id(&self, desktop: &Desktop) -> Result<GUID> {
+        match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(_) => get_idesktop_guid(&self.get_idesktop(desktop)?),
+            DesktopInternal::Guid(guid) => Ok(guid),
+            DesktopInternal::IndexGuid(_, guid) => Ok(guid),
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_desktops(&self) -> Result<Vec<Desktop>> {
+        let desktops = self.get_idesktops_array()?;
+        let count = unsafe { desktops.GetCount()? };
+        let mut result = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let id = get_idesktop_guid(&unsafe { desktops.GetAt(i)? })?;
+            result.push(DesktopInternal::IndexGuid(i, id).into());
+        }
+        Ok(result)
+    }
+
+    #[apply(retry_function)]
+    fn get_desktop_count(&self) -> Result<u32> {
+        let mut count = 0;
+        unsafe {
+            self.get_manager_internal()?
+                .get_desktop_count(&mut count)
+                .as_result()?;
+        }
+        Ok(count)
+    }
+
+    #[apply(retry_function)]
+    fn get_current_desktop(&self) -> Result<Desktop> {
+        let mut desktop = None;
+        unsafe {
+            self.get_manager_internal()?
+                .get_current_desktop(&mut desktop)
+                .as_result()?
+        }
+        let desktop = desktop.ok_or(Error::ComAllocatedNullPtr)?;
+        Ok(get_idesktop_guid(&desktop)?.into())
+    }
+
+    #[apply(retry_function)]
+    fn switch_desktop(&self, desktop: &Desktop) -> Result<()> {
+        let desktop = self.get_idesktop(desktop)?;
+        unsafe {
+            self.get_manager_internal()?
+                .switch_desktop(ComIn::new(&desktop))
+                .as_result()
+        }
+    }
+
+    #[apply(retry_function)]
+    fn create_desktop(&self) -> Result<Desktop> {
+        let mut desktop = None;
+        unsafe {
+            self.get_manager_internal()?
+                .create_desktop(&mut desktop)
+                .as_result()?
+        }
+        let desktop = desktop.ok_or(Error::ComAllocatedNullPtr)?;
+        let id = get_idesktop_guid(&desktop)?;
+        let index = self.get_desktop_index_by_guid(&id)?;
+        Ok(DesktopInternal::IndexGuid(index, id).into())
+    }
+
+    #[apply(retry_function)]
+    fn remove_desktop(&self, desktop: &Desktop, fallback_desktop: &Desktop) -> Result<()> {
+        let desktop = self.get_idesktop(desktop)?;
+        let fb_desktop = self.get_idesktop(fallback_desktop)?;
+        unsafe {
+            self.get_manager_internal()?
+                .remove_desktop(ComIn::new(&desktop), ComIn::new(&fb_desktop))
+                .as_result()
+        }
+    }
+
+    fn move_desktop(&self, _desktop: &Desktop, _index: u32) -> Result<()> {
+        Err(Error::NotSupported)
+    }
+
+    #[apply(retry_function)]
+    fn get_adjacent_desktop(
+        &self,
+        desktop: &Desktop,
+        direction: Direction,
+    ) -> Result<Option<Desktop>> {
+        let desktop = self.get_idesktop(desktop)?;
+        let direction = match direction {
+            Direction::Left => 3,
+            Direction::Right => 4,
+        };
+        let mut adjacent = None;
+        let result = unsafe {
+            self.get_manager_internal()?
+                .get_adjacent_desktop(ComIn::new(&desktop), direction, &mut adjacent)
+                .as_result()
+        };
+        match result {
+            // There is no desktop in that direction
+            Err(Error::ComElementNotFound) => return Ok(None),
+            result => result?,
+        }
+        let adjacent = adjacent.ok_or(Error::ComAllocatedNullPtr)?;
+        Ok(Some(get_idesktop_guid(&adjacent)?.into()))
+    }
+
+    #[apply(retry_function)]
+    fn get_desktop_name(&self, desktop: &Desktop) -> Result<String> {
+        let desktop = self
+            .get_idesktop(desktop)?
+            .cast::<IVirtualDesktop2>()
+            .map_err(|er| not_supported(er.into()))?;
+        let mut name = HSTRING::default();
+        unsafe {
+            desktop.get_name(&mut name).as_result()?;
+        }
+        Ok(name.to_string())
+    }
+
+    #[apply(retry_function)]
+    fn set_desktop_name(&self, desktop: &Desktop, name: &str) -> Result<()> {
+        let manager_internal2 = self.get_manager_internal2()?;
+        let desktop = self.get_idesktop(desktop)?;
+        unsafe {
+            manager_internal2
+                .set_name(ComIn::new(&desktop), HSTRING::from(name))
+                .as_result()
+        }
+    }
+
+    fn get_desktop_wallpaper(&self, _desktop: &Desktop) -> Result<String> {
+        Err(Error::NotSupported)
+    }
+
+    fn set_desktop_wallpaper(&self, _desktop: &Desktop, _path: &str) -> Result<()> {
+        Err(Error::NotSupported)
+    }
+
+    fn set_wallpaper_for_all(&self, _path: &str) -> Result<()> {
+        Err(Error::NotSupported)
+    }
+
+    #[apply(retry_function)]
+    fn is_window_on_desktop(&self, window: &Window, desktop: &Desktop) -> Result<bool> {
+        let desktop_win = self.get_desktop_by_window(window)?;
+        Ok(self.get_desktop_id(&desktop_win)? == self.get_desktop_id(desktop)?)
+    }
+
+    fn is_window_on_current_desktop(&self, window: &Window) -> Result<bool> {
+        self.shared.is_window_on_current_desktop(window)
+    }
+
+    #[apply(retry_function)]
+    fn can_move_window(&self, window: &Window) -> Result<bool> {
+        let view = self.shared.get_iapplication_view_for_hwnd(&window.hwnd())?;
+        let mut can_move = 0;
+        unsafe {
+            self.get_manager_internal()?
+                .can_move_view_between_desktops(ComIn::new(&view), &mut can_move)
+                .as_result()?
+        }
+        Ok(can_move != 0)
+    }
+
+    #[apply(retry_function)]
+    fn move_window_to_desktop(&self, window: &Window, desktop: &Desktop) -> Result<()> {
+        if !self.can_move_window(window)? {
+            return Err(Error::WindowNotMovable(window.hwnd()));
+        }
+        let view = self.shared.get_iapplication_view_for_hwnd(&window.hwnd())?;
+        let target = ErrorTarget::from(*desktop);
+        let desktop = self.get_idesktop(desktop)?;
+        unsafe {
+            self.get_manager_internal()?
+                .move_view_to_desktop(ComIn::new(&view), ComIn::new(&desktop))
+                .as_result()
+                .map_err(|er| match er {
+                    Error::ComElementNotFound => Error::DesktopNotFound(target),
+                    _ => er,
+                })
+        }
+    }
+
+    fn get_desktop_by_window(&self, window: &Window) -> Result<Desktop> {
+        self.shared.get_desktop_by_window(window)
+    }
+
+    fn get_all_windows(&self) -> Result<Vec<DesktopWindow>> {
+        self.shared.get_all_windows()
+    }
+
+    fn get_all_windows_by_zorder(&self) -> Result<Vec<DesktopWindow>> {
+        self.shared.get_all_windows_by_zorder()
+    }
+
+    fn get_focused_window(&self) -> Result<Option<DesktopWindow>> {
+        self.shared.get_focused_window()
+    }
+
+    #[apply(retry_function)]
+    fn get_last_active_window(&self, desktop: &Desktop) -> Result<Option<DesktopWindow>> {
+        let desktop: Desktop = self.get_desktop_id(desktop)?.into();
+        let is_current = desktop == self.get_current_desktop()?;
+        self.shared.get_last_active_window_of(desktop, is_current)
+    }
+
+    fn get_window_title(&self, window: &Window) -> Result<String> {
+        self.shared.get_window_title(window)
+    }
+
+    fn get_window_info(&self, window: &Window) -> Result<WindowInfo> {
+        self.shared.get_window_info(window)
+    }
+
+    #[apply(retry_function)]
+    fn activate_window(&self, window: &Window) -> Result<()> {
+        let view = self.shared.get_iapplication_view_for_hwnd(&window.hwnd())?;
+
+        // Pinned windows are on every desktop, stay on the current one
+        if !self.is_pinned_window(window)? && !self.is_pinned_app(window)? {
+            let desktop = self.get_desktop_by_window(window)?;
+            self.switch_desktop(&desktop)?;
+        }
+        unsafe {
+            view.switch_to().as_result()?;
+            view.set_focus().as_result()
+        }
+    }
+
+    fn is_pinned_window(&self, window: &Window) -> Result<bool> {
+        self.shared.is_pinned_window(window)
+    }
+
+    fn pin_window(&self, window: &Window) -> Result<()> {
+        self.shared.pin_window(window)
+    }
+
+    fn unpin_window(&self, window: &Window) -> Result<()> {
+        self.shared.unpin_window(window)
+    }
+
+    fn is_pinned_app(&self, window: &Window) -> Result<bool> {
+        self.shared.is_pinned_app(window)
+    }
+
+    fn pin_app(&self, window: &Window) -> Result<()> {
+        self.shared.pin_app(window)
+    }
+
+    fn unpin_app(&self, window: &Window) -> Result<()> {
+        self.shared.unpin_app(window)
+    }
+
+    fn get_app_id(&self, window: &Window) -> Result<String> {
+        self.shared.get_app_id(window)
+    }
+
+    fn is_app_id_pinned(&self, app_id: &str) -> Result<bool> {
+        self.shared.is_app_id_pinned(app_id)
+    }
+
+    fn pin_app_id(&self, app_id: &str) -> Result<()> {
+        self.shared.pin_app_id(app_id)
+    }
+
+    fn unpin_app_id(&self, app_id: &str) -> Result<()> {
+        self.shared.unpin_app_id(app_id)
+    }
+
+    fn get_windows_by_app_id(&self, app_id: &str) -> Result<Vec<DesktopWindow>> {
+        self.shared.get_windows_by_app_id(app_id)
+    }
+
+    fn register_for_notifications(
+        &self,
+        sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+        mask: EventMask,
+    ) -> Result<u32> {
+        // Views are tracked only for the window events
+        let windows = match mask.intersects(EventMask::WINDOWS) {
+            true => self.get_all_windows().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let (cookie, notification) = self
+            .shared
+            .register_notification(VirtualDesktopNotification::new(sender, mask, windows).into())?;
+        self.notifications
+            .try_borrow_mut()
+            .map_err(|_| Error::InternalBorrowError)?
+            .insert(cookie, notification);
+        Ok(cookie)
+    }
+
+    fn unregister_for_notifications(&self, cookie: u32) -> Result<()> {
+        // The notification object is kept alive until it's unregistered
+        let notification = self
+            .notifications
+            .try_borrow_mut()
+            .map_err(|_| Error::InternalBorrowError)?
+            .remove(&cookie);
+        let result = self.shared.unregister_notification(cookie);
+        drop(notification);
+        result
+    }
+
+    fn is_connected(&self) -> bool {
+        // Same check as on Windows 11, the count is zero if not connected
+        let mut count = 0;
+        match self.get_manager_internal() {
+            Ok(manager_internal) => {
+                let res = unsafe { manager_internal.get_desktop_count(&mut count).as_result() };
+                res.is_ok() && count != 0
+            }
+            Err(_) => false,
+        }
+    }
+
+    #[apply(retry_function)]
+    fn get_capabilities(&self) -> Result<Capabilities> {
+        let desktop_names = match self.get_manager_internal2() {
+            Ok(_) => true,
+            Err(Error::NotSupported) => false,
+            Err(er) => return Err(er),
+        };
+        Ok(Capabilities {
+            desktop_names,
+            ..Capabilities::WINDOWS_10
+        })
+    }
+}
+
+/// Interfaces missing from the build are reported as `NotSupported`
+fn not_supported(er: Error) -> Error {
+    match er {
+        Error::ComError(error) if error.hresult == E_NOINTERFACE => Error::NotSupported,
+        er => er,
+    }
+}
+
+fn get_idesktop_guid(desktop: &IVirtualDesktop) -> Result<GUID> {
+    let mut guid = GUID::default();
+    unsafe { desktop.get_id(&mut guid).as_result()? }
+    Ok(guid)
+}
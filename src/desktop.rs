@@ -1,9 +1,123 @@
-use crate::interfaces::ComIn;
+use super::*;
+use std::fmt::Debug;
+use windows::core::GUID;
 
-use super::comobjects::*;
-use super::{interfaces::IVirtualDesktop, *};
-use std::{convert::TryFrom, fmt::Debug};
-use windows::{core::GUID, Win32::Foundation::HWND};
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum DesktopInternal {
+    Index(u32),
+    Guid(GUID),
+    IndexGuid(u32, GUID),
+}
+
+// Impl from u32 to DesktopTest
+impl From<u32> for DesktopInternal {
+    fn from(index: u32) -> Self {
+        DesktopInternal::Index(index)
+    }
+}
+
+// Impl from i32 to DesktopTest
+impl From<i32> for DesktopInternal {
+    fn from(index: i32) -> Self {
+        DesktopInternal::Index(index as u32)
+    }
+}
+
+// Impl from GUID to DesktopTest
+impl From<GUID> for DesktopInternal {
+    fn from(guid: GUID) -> Self {
+        DesktopInternal::Guid(guid)
+    }
+}
+
+// Impl from &GUID to DesktopTest
+impl From<&GUID> for DesktopInternal {
+    fn from(guid: &GUID) -> Self {
+        DesktopInternal::Guid(*guid)
+    }
+}
+
+/// Direction of the adjacent desktop, as in the task view
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Towards the first desktop
+    Left,
+
+    /// Towards the last desktop
+    Right,
+}
+
+/// What to do when switching past the first or last desktop
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum EdgePolicy {
+    /// Stay on the current desktop
+    #[default]
+    Stop,
+
+    /// Continue from the other end
+    Wrap,
+
+    /// Create a new desktop at the end, when switching past the first desktop
+    /// this is same as `Stop`
+    Create,
+}
+
+/// Operations and events supported by the shell, see `get_capabilities`
+///
+/// Unsupported operations fail with `Error::NotSupported`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Desktops can be named, `Desktop::get_name` and `Desktop::set_name`
+    pub desktop_names: bool,
+
+    /// Desktops have their own wallpapers, `Desktop::get_wallpaper` and the
+    /// setters
+    pub desktop_wallpapers: bool,
+
+    /// Desktops can be reordered with `move_desktop`, also `swap_desktops`
+    /// and `sort_desktops_by_name` need this
+    pub move_desktop: bool,
+
+    /// `DesktopMoved`, `DesktopNameChanged`, `DesktopWallpaperChanged`,
+    /// `DesktopSwitched` and `RemoteDesktopConnected` events are sent
+    pub desktop_events: bool,
+}
+
+impl Capabilities {
+    /// Everything is supported, Windows 11
+    pub const ALL: Capabilities = Capabilities {
+        desktop_names: true,
+        desktop_wallpapers: true,
+        move_desktop: true,
+        desktop_events: true,
+    };
+
+    /// Windows 10, desktops can be named but not reordered
+    pub const WINDOWS_10: Capabilities = Capabilities {
+        desktop_names: true,
+        desktop_wallpapers: false,
+        move_desktop: false,
+        desktop_events: false,
+    };
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::ALL
+    }
+}
+
+/// Window and the desktop it is on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DesktopWindow {
+    pub window: Window,
+
+    /// Desktop of the window, `None` if the window is pinned
+    pub desktop: Option<Desktop>,
+
+    /// Is the window or its app pinned, i.e. shown on all desktops
+    pub is_pinned: bool,
+}
 
 /// You can construct Desktop instance with `get_desktop(5)` by index or GUID.
 #[derive(Copy, Clone, Debug)]
@@ -25,8 +139,10 @@ impl PartialEq for Desktop {
             (DesktopInternal::IndexGuid(a, _), DesktopInternal::Index(b)) => a == b,
             (DesktopInternal::Guid(a), DesktopInternal::IndexGuid(_, b)) => a == b,
             (DesktopInternal::IndexGuid(_, a), DesktopInternal::Guid(b)) => a == b,
-            _ => with_com_objects(move |f| Ok(f.get_desktop_id(&a)? == f.get_desktop_id(&b)?))
-                .unwrap_or(false),
+            _ => with_com_objects(move |f| {
+                Ok(f.get_desktop_id(&a.into())? == f.get_desktop_id(&b.into())?)
+            })
+            .unwrap_or(false),
         }
     }
 }
@@ -71,64 +187,86 @@ impl From<Desktop> for DesktopInternal {
     }
 }
 
-impl TryFrom<IVirtualDesktop> for Desktop {
-    type Error = Error;
-
-    fn try_from(desktop: IVirtualDesktop) -> Result<Self> {
-        Ok(Desktop(DesktopInternal::try_from(&desktop)?))
-    }
-}
-impl<'a> TryFrom<&'a ComIn<'a, IVirtualDesktop>> for Desktop {
-    type Error = Error;
-
-    fn try_from(desktop: &'a ComIn<'a, IVirtualDesktop>) -> Result<Self> {
-        Ok(Desktop(DesktopInternal::try_from(desktop)?))
+impl From<Desktop> for ErrorTarget {
+    fn from(desktop: Desktop) -> Self {
+        match desktop.0 {
+            DesktopInternal::Index(index) => ErrorTarget::DesktopIndex(index),
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => {
+                ErrorTarget::DesktopId(id)
+            }
+        }
     }
 }
-impl<'a> TryFrom<ComIn<'a, IVirtualDesktop>> for Desktop {
-    type Error = Error;
 
-    fn try_from(desktop: ComIn<'a, IVirtualDesktop>) -> Result<Self> {
-        Ok(Desktop(DesktopInternal::try_from(&desktop)?))
-    }
-}
 impl Desktop {
     /// Get the GUID of the desktop
     pub fn get_id(&self) -> Result<GUID> {
-        let internal = self.0.clone();
-        with_com_objects(move |o| o.get_desktop_id(&internal))
+        let desktop = *self;
+        with_com_objects(move |o| o.get_desktop_id(&desktop))
     }
 
     pub fn get_index(&self) -> Result<u32> {
-        let internal = self.0.clone();
-        with_com_objects(move |o| o.get_desktop_index(&internal))
+        let desktop = *self;
+        with_com_objects(move |o| o.get_desktop_index(&desktop))
     }
 
     /// Get desktop name
     pub fn get_name(&self) -> Result<String> {
-        let internal = self.0.clone();
-        with_com_objects(move |o| o.get_desktop_name(&internal))
+        let desktop = *self;
+        with_com_objects(move |o| o.get_desktop_name(&desktop))
     }
 
     /// Set desktop name
     pub fn set_name(&self, name: &str) -> Result<()> {
-        let internal = self.0.clone();
+        let desktop = *self;
         let name_ = name.to_owned();
-        with_com_objects(move |o| o.set_desktop_name(&internal, &name_))
+        with_com_objects(move |o| o.set_desktop_name(&desktop, &name_))
     }
 
     /// Get desktop wallpaper path
     pub fn get_wallpaper(&self) -> Result<String> {
-        let internal = self.0.clone();
-        with_com_objects(move |o| o.get_desktop_wallpaper(&internal))
+        let desktop = *self;
+        with_com_objects(move |o| o.get_desktop_wallpaper(&desktop))
     }
 
     /// Set desktop wallpaper path
     pub fn set_wallpaper(&self, path: &str) -> Result<()> {
-        let internal = self.0.clone();
+        let desktop = *self;
         let path_ = path.to_owned();
-        with_com_objects(move |o| o.set_desktop_wallpaper(&internal, &path_))
+        with_com_objects(move |o| o.set_desktop_wallpaper(&desktop, &path_))
     }
+
+    /// Get windows on the desktop, pinned windows are not included
+    pub fn get_windows(&self) -> Result<Vec<Window>> {
+        let desktop = *self;
+        with_com_objects(move |o| windows_on_desktop(o, &desktop, o.get_all_windows()?))
+    }
+
+    /// Get windows on the desktop topmost first, pinned windows are not
+    /// included
+    pub fn get_windows_by_zorder(&self) -> Result<Vec<Window>> {
+        let desktop = *self;
+        with_com_objects(move |o| windows_on_desktop(o, &desktop, o.get_all_windows_by_zorder()?))
+    }
+
+    /// Move desktop to the index, other desktops are shifted
+    pub fn move_to(&self, index: u32) -> Result<()> {
+        let desktop = *self;
+        with_com_objects(move |o| o.move_desktop(&desktop, index))
+    }
+}
+
+fn windows_on_desktop(
+    o: &dyn VirtualDesktopBackend,
+    desktop: &Desktop,
+    windows: Vec<DesktopWindow>,
+) -> Result<Vec<Window>> {
+    let desktop = Some(o.get_desktop_id(desktop)?.into());
+    Ok(windows
+        .into_iter()
+        .filter(|w| w.desktop == desktop)
+        .map(|w| w.window)
+        .collect())
 }
 
 /// Get desktop by index or GUID
@@ -152,57 +290,283 @@ where
     T: Into<Desktop>,
     T: Send + 'static + Copy,
 {
-    with_com_objects(move |o| o.switch_desktop(&desktop.into().into()))
+    with_com_objects(move |o| o.switch_desktop(&desktop.into()))
 }
 
 /// Remove desktop by index or GUID
 pub fn remove_desktop<T>(desktop: T, fallback_desktop: T) -> Result<()>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| o.remove_desktop(&desktop.into(), &fallback_desktop.into()))
+}
+
+/// Move desktop by index or GUID to the new index
+///
+/// Other desktops are shifted, e.g. moving the first desktop to index 2 makes
+/// the second desktop first.
+pub fn move_desktop<T>(desktop: T, new_index: u32) -> Result<()>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| o.move_desktop(&desktop.into(), new_index))
+}
+
+/// Swap places of two desktops by index or GUID
+pub fn swap_desktops<T>(desktop_a: T, desktop_b: T) -> Result<()>
 where
     T: Into<Desktop>,
     T: Send + 'static + Copy,
 {
     with_com_objects(move |o| {
-        o.remove_desktop(&desktop.into().into(), &fallback_desktop.into().into())
+        // Resolve GUIDs first, indices change while moving
+        let a: Desktop = o.get_desktop_id(&desktop_a.into())?.into();
+        let b: Desktop = o.get_desktop_id(&desktop_b.into())?.into();
+        let index_a = o.get_desktop_index(&a)?;
+        let index_b = o.get_desktop_index(&b)?;
+        if index_a != index_b {
+            o.move_desktop(&a, index_b)?;
+            o.move_desktop(&b, index_a)?;
+        }
+        Ok(())
+    })
+}
+
+/// Sort all desktops by name, case insensitive
+///
+/// Desktops with same name keep their order.
+pub fn sort_desktops_by_name() -> Result<()> {
+    with_com_objects(|o| {
+        let mut desktops = o
+            .get_desktops()?
+            .iter()
+            .map(|d| Ok((o.get_desktop_name(d)?.to_lowercase(), o.get_desktop_id(d)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut order = desktops.iter().map(|(_, id)| *id).collect::<Vec<_>>();
+        desktops.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (index, (_, id)) in desktops.iter().enumerate() {
+            let current = order.iter().position(|d| d == id).unwrap_or(index);
+            if current != index {
+                o.move_desktop(&(*id).into(), index as u32)?;
+                let id = order.remove(current);
+                order.insert(index, id);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Get the desktop next to the desktop by index or GUID
+///
+/// Returns `None` if there is no desktop in that direction.
+pub fn get_adjacent_desktop<T>(desktop: T, direction: Direction) -> Result<Option<Desktop>>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| o.get_adjacent_desktop(&desktop.into(), direction))
+}
+
+/// Switch to the desktop right of the current desktop, returns the desktop
+/// that is current afterwards
+pub fn switch_desktop_next(policy: EdgePolicy) -> Result<Desktop> {
+    switch_desktop_adjacent(Direction::Right, policy)
+}
+
+/// Switch to the desktop left of the current desktop, returns the desktop
+/// that is current afterwards
+pub fn switch_desktop_previous(policy: EdgePolicy) -> Result<Desktop> {
+    switch_desktop_adjacent(Direction::Left, policy)
+}
+
+fn switch_desktop_adjacent(direction: Direction, policy: EdgePolicy) -> Result<Desktop> {
+    with_com_objects(move |o| {
+        let current = o.get_current_desktop()?;
+        let target = match (o.get_adjacent_desktop(&current, direction)?, policy) {
+            (Some(desktop), _) => desktop,
+            (None, EdgePolicy::Stop) => return Ok(current),
+            (None, EdgePolicy::Wrap) => {
+                let desktops = o.get_desktops()?;
+                let other_end = match direction {
+                    Direction::Left => desktops.last(),
+                    Direction::Right => desktops.first(),
+                };
+                *other_end.ok_or(Error::DesktopNotFound(ErrorTarget::DesktopIndex(0)))?
+            }
+            (None, EdgePolicy::Create) => match direction {
+                Direction::Left => return Ok(current),
+                Direction::Right => o.create_desktop()?,
+            },
+        };
+        o.switch_desktop(&target)?;
+        Ok(target)
+    })
+}
+
+/// Set wallpaper of all desktops, returns the desktops that have the wallpaper
+/// afterwards
+///
+/// The path must be absolute and the file must exist.
+pub fn set_wallpaper_for_all(path: &str) -> Result<Vec<Desktop>> {
+    let file = std::path::Path::new(path);
+    if !file.is_absolute() || !file.is_file() {
+        return Err(Error::InvalidWallpaperPath);
+    }
+    let path_ = path.to_owned();
+    with_com_objects(move |o| {
+        o.set_wallpaper_for_all(&path_)?;
+        let mut desktops = Vec::new();
+        for desktop in o.get_desktops()? {
+            if o.get_desktop_wallpaper(&desktop)? == path_ {
+                desktops.push(desktop);
+            }
+        }
+        Ok(desktops)
     })
 }
 
 /// Is window on desktop by index or GUID
-pub fn is_window_on_desktop<T>(desktop: T, hwnd: HWND) -> Result<bool>
+pub fn is_window_on_desktop<T, W>(desktop: T, window: W) -> Result<bool>
 where
     T: Into<Desktop>,
     T: Send + 'static + Copy,
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.is_window_on_desktop(&window, &desktop.into()))
+}
+
+/// Can the window be moved to another desktop
+///
+/// E.g. some system windows, UWP hosts and elevated windows can not be moved.
+pub fn can_move_window<W>(window: W) -> Result<bool>
+where
+    W: Into<Window>,
 {
-    with_com_objects(move |o| o.is_window_on_desktop(&hwnd, &desktop.into().into()))
+    let window = window.into();
+    with_com_objects(move |o| o.can_move_window(&window))
 }
 
 /// Move window to desktop by index or GUID
-pub fn move_window_to_desktop<T>(desktop: T, hwnd: &HWND) -> Result<()>
+///
+/// Returns `Error::WindowNotMovable` if the window can not be moved.
+pub fn move_window_to_desktop<T, W>(desktop: T, window: W) -> Result<()>
 where
     T: Into<Desktop>,
     T: Send + 'static + Copy,
+    W: Into<Window>,
 {
-    let hwnd = hwnd.clone();
-    with_com_objects(move |o| o.move_window_to_desktop(&hwnd, &desktop.into().into()))
+    let window = window.into();
+    with_com_objects(move |o| o.move_window_to_desktop(&window, &desktop.into()))
 }
 
 /// Create desktop
 pub fn create_desktop() -> Result<Desktop> {
-    with_com_objects(|o| o.create_desktop().map(Desktop))
+    with_com_objects(|o| o.create_desktop())
 }
 
 /// Get current desktop
 pub fn get_current_desktop() -> Result<Desktop> {
-    with_com_objects(|o| o.get_current_desktop().map(Desktop))
+    with_com_objects(|o| o.get_current_desktop())
 }
 
 /// Get all desktops
 pub fn get_desktops() -> Result<Vec<Desktop>> {
-    with_com_objects(|o| Ok(o.get_desktops()?.into_iter().map(Desktop).collect()))
+    with_com_objects(|o| o.get_desktops())
 }
 
 /// Get desktop by window
-pub fn get_desktop_by_window(hwnd: HWND) -> Result<Desktop> {
-    with_com_objects(move |o| o.get_desktop_by_window(&hwnd).map(Desktop))
+pub fn get_desktop_by_window<W>(window: W) -> Result<Desktop>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.get_desktop_by_window(&window))
+}
+
+/// Get all windows with their desktop and pinned state
+pub fn get_all_windows() -> Result<Vec<DesktopWindow>> {
+    with_com_objects(|o| o.get_all_windows())
+}
+
+/// Get the focused window and its desktop, `None` if no window is focused
+pub fn get_focused_window() -> Result<Option<DesktopWindow>> {
+    with_com_objects(|o| o.get_focused_window())
+}
+
+/// Get the window that was active last on the desktop by index or GUID
+///
+/// Useful for restoring the focus after switching desktops. Pinned windows are
+/// not considered.
+pub fn get_last_active_window<T>(desktop: T) -> Result<Option<DesktopWindow>>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| o.get_last_active_window(&desktop.into()))
+}
+
+/// Activate window, switches to the desktop of the window and brings it to
+/// the front
+///
+/// Pinned windows are activated on the current desktop.
+pub fn activate_window<W>(window: W) -> Result<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.activate_window(&window))
+}
+
+/// Get the application user model ID of the window
+pub fn get_app_id<W>(window: W) -> Result<String>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.get_app_id(&window))
+}
+
+/// Is the app pinned by application user model ID
+pub fn is_app_id_pinned(app_id: &str) -> Result<bool> {
+    let app_id = app_id.to_owned();
+    with_com_objects(move |o| o.is_app_id_pinned(&app_id))
+}
+
+/// Pin the app by application user model ID
+///
+/// The app does not have to be running, its windows are pinned when opened.
+pub fn pin_app_id(app_id: &str) -> Result<()> {
+    let app_id = app_id.to_owned();
+    with_com_objects(move |o| o.pin_app_id(&app_id))
+}
+
+/// Unpin the app by application user model ID
+pub fn unpin_app_id(app_id: &str) -> Result<()> {
+    let app_id = app_id.to_owned();
+    with_com_objects(move |o| o.unpin_app_id(&app_id))
+}
+
+/// Get all windows of the app by application user model ID
+pub fn get_windows_by_app_id(app_id: &str) -> Result<Vec<DesktopWindow>> {
+    let app_id = app_id.to_owned();
+    with_com_objects(move |o| o.get_windows_by_app_id(&app_id))
+}
+
+/// Get the operations and events supported by the shell
+///
+/// # Example
+///
+/// ```rust,no_run
+/// if winvd::get_capabilities().unwrap().desktop_wallpapers {
+///     winvd::get_desktop(0).set_wallpaper("C:\\wallpaper.jpg").unwrap();
+/// }
+/// ```
+pub fn get_capabilities() -> Result<Capabilities> {
+    with_com_objects(|o| o.get_capabilities())
 }
 
 /// Get desktop count
@@ -210,36 +574,64 @@ pub fn get_desktop_count() -> Result<u32> {
     with_com_objects(|o| o.get_desktop_count())
 }
 
-pub fn is_window_on_current_desktop(hwnd: HWND) -> Result<bool> {
-    with_com_objects(move |o| o.is_window_on_current_desktop(&hwnd))
+pub fn is_window_on_current_desktop<W>(window: W) -> Result<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.is_window_on_current_desktop(&window))
 }
 
 /// Is window pinned?
-pub fn is_pinned_window(hwnd: HWND) -> Result<bool> {
-    with_com_objects(move |o| o.is_pinned_window(&hwnd))
+pub fn is_pinned_window<W>(window: W) -> Result<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.is_pinned_window(&window))
 }
 
 /// Pin window
-pub fn pin_window(hwnd: HWND) -> Result<()> {
-    with_com_objects(move |o| o.pin_window(&hwnd))
+pub fn pin_window<W>(window: W) -> Result<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.pin_window(&window))
 }
 
 /// Unpin window
-pub fn unpin_window(hwnd: HWND) -> Result<()> {
-    with_com_objects(move |o| o.unpin_window(&hwnd))
+pub fn unpin_window<W>(window: W) -> Result<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.unpin_window(&window))
 }
 
 /// Is pinned app
-pub fn is_pinned_app(hwnd: HWND) -> Result<bool> {
-    with_com_objects(move |o| o.is_pinned_app(&hwnd))
+pub fn is_pinned_app<W>(window: W) -> Result<bool>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.is_pinned_app(&window))
 }
 
 /// Pin app
-pub fn pin_app(hwnd: HWND) -> Result<()> {
-    with_com_objects(move |o| o.pin_app(&hwnd))
+pub fn pin_app<W>(window: W) -> Result<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.pin_app(&window))
 }
 
 /// Unpin app
-pub fn unpin_app(hwnd: HWND) -> Result<()> {
-    with_com_objects(move |o| o.unpin_app(&hwnd))
+pub fn unpin_app<W>(window: W) -> Result<()>
+where
+    W: Into<Window>,
+{
+    let window = window.into();
+    with_com_objects(move |o| o.unpin_app(&window))
 }
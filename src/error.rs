@@ -0,0 +1,303 @@
+use std::fmt;
+use windows::{core::GUID, core::HRESULT, Win32::Foundation::HWND};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// Window is not found
+    WindowNotFound(HWND),
+
+    /// Window can not be moved between desktops, e.g. system windows, UWP hosts
+    /// or elevated windows
+    WindowNotMovable(HWND),
+
+    /// Desktop with given index or ID is not found
+    DesktopNotFound(ErrorTarget),
+
+    /// Creationg of desktop failed
+    CreateDesktopFailed,
+
+    /// Remove desktop failed
+    RemoveDesktopFailed,
+
+    /// Desktop index given for moving is out of range
+    InvalidDesktopIndex(u32),
+
+    /// Wallpaper path is not absolute or the file does not exist
+    InvalidWallpaperPath,
+
+    /// Layout could not be read or written, contains the parser message
+    InvalidLayout(String),
+
+    /// Unable to create service, ensure that explorer.exe is running
+    ClassNotRegistered,
+
+    /// Unable to connect to service
+    RpcServerNotAvailable,
+
+    /// Com is not initialized, call CoInitializeEx or CoIncrementMTAUsage
+    ComNotInitialized,
+
+    /// Com object not connected
+    ComObjectNotConnected,
+
+    /// Generic element not found
+    ComElementNotFound,
+
+    /// Some unhandled COM error, with the operation that failed
+    ComError(ComError),
+
+    /// This should not happen, this means that successful COM call allocated a
+    /// null pointer, in this case it is an error in the COM service, or it's
+    /// usage.
+    ComAllocatedNullPtr,
+
+    /// Borrow error
+    InternalBorrowError,
+
+    /// Selected backend is not available on this platform, the COM backend
+    /// works only on Windows. Use `set_backend` to select the simulated shell.
+    BackendNotAvailable,
+
+    /// The virtual desktop interfaces of this Windows build, or of its update,
+    /// are not known, contains the build number
+    UnsupportedBuild { build: u32 },
+
+    /// Windows build could not be read from the registry, contains the reason
+    BuildDetectionFailed(String),
+
+    /// Operation is not supported on this Windows build, e.g. wallpapers on
+    /// Windows 10, see `get_capabilities`
+    NotSupported,
+
+    /// Thread of a listener or of the event hub could not be started,
+    /// contains the system message
+    ThreadSpawnFailed(String),
+}
+
+/// Desktop or window the failed operation was called with
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorTarget {
+    DesktopIndex(u32),
+    DesktopId(GUID),
+    Window(HWND),
+}
+
+/// Unhandled COM error
+///
+/// The operation and the target are set by the COM backend, e.g.
+/// `get_desktop_name` of `DesktopIndex(2)`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ComError {
+    pub hresult: HRESULT,
+    pub operation: Option<&'static str>,
+    pub target: Option<ErrorTarget>,
+}
+
+impl ComError {
+    pub fn new(hresult: HRESULT) -> Self {
+        ComError {
+            hresult,
+            operation: None,
+            target: None,
+        }
+    }
+
+    /// System message of the HRESULT, empty if there is none
+    pub fn message(&self) -> String {
+        #[cfg(windows)]
+        return self.hresult.message().to_string();
+
+        #[cfg(not(windows))]
+        String::new()
+    }
+}
+
+// HRESULTs of a busy or restarting shell
+const RPC_E_CALL_REJECTED: HRESULT = HRESULT(0x80010001_u32 as i32);
+const RPC_E_SERVERCALL_RETRYLATER: HRESULT = HRESULT(0x8001010A_u32 as i32);
+
+// HRESULTs of elements that do not exist
+const ERROR_NOT_FOUND: HRESULT = HRESULT(0x80070490_u32 as i32);
+const ERROR_INVALID_WINDOW_HANDLE: HRESULT = HRESULT(0x80070578_u32 as i32);
+
+impl Error {
+    /// Could the operation succeed if it is tried again, e.g. after
+    /// explorer.exe has restarted
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::ClassNotRegistered
+            | Error::RpcServerNotAvailable
+            | Error::ComObjectNotConnected
+            | Error::ComAllocatedNullPtr
+            | Error::ComNotInitialized => true,
+            Error::ComError(error) => {
+                error.hresult == RPC_E_CALL_REJECTED || error.hresult == RPC_E_SERVERCALL_RETRYLATER
+            }
+            _ => false,
+        }
+    }
+
+    /// Does the desktop, window or other element not exist
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::WindowNotFound(_) | Error::DesktopNotFound(_) | Error::ComElementNotFound => {
+                true
+            }
+            Error::ComError(error) => {
+                error.hresult == ERROR_NOT_FOUND || error.hresult == ERROR_INVALID_WINDOW_HANDLE
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the operation and the target of a `ComError`, unless an inner
+    /// operation has set them already
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn with_context(self, operation: &'static str, target: Option<ErrorTarget>) -> Self {
+        match self {
+            Error::ComError(mut error) if error.operation.is_none() => {
+                error.operation = Some(operation);
+                error.target = error.target.or(target);
+                Error::ComError(error)
+            }
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for ErrorTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorTarget::DesktopIndex(index) => write!(f, "desktop {}", index),
+            ErrorTarget::DesktopId(id) => write!(f, "desktop {:?}", id),
+            ErrorTarget::Window(hwnd) => write!(f, "window {:#x}", hwnd.0),
+        }
+    }
+}
+
+impl fmt::Display for ComError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.operation, self.target) {
+            (Some(operation), Some(target)) => write!(f, "{} of {} failed", operation, target)?,
+            (Some(operation), None) => write!(f, "{} failed", operation)?,
+            (None, _) => write!(f, "COM call failed")?,
+        }
+        write!(f, " with {}", self.hresult)?;
+        let message = self.message();
+        if !message.is_empty() {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WindowNotFound(hwnd) => {
+                write!(f, "{} not found", ErrorTarget::Window(*hwnd))
+            }
+            Error::WindowNotMovable(hwnd) => {
+                write!(
+                    f,
+                    "{} can not be moved between desktops",
+                    ErrorTarget::Window(*hwnd)
+                )
+            }
+            Error::DesktopNotFound(desktop) => write!(f, "{} not found", desktop),
+            Error::CreateDesktopFailed => write!(f, "creating the desktop failed"),
+            Error::RemoveDesktopFailed => write!(f, "removing the desktop failed"),
+            Error::InvalidDesktopIndex(index) => {
+                write!(f, "desktop index {} is out of range", index)
+            }
+            Error::InvalidWallpaperPath => {
+                write!(
+                    f,
+                    "wallpaper path is not absolute or the file does not exist"
+                )
+            }
+            Error::InvalidLayout(message) => write!(f, "invalid layout: {}", message),
+            Error::ClassNotRegistered => {
+                write!(
+                    f,
+                    "virtual desktop service is not registered, is explorer.exe running?"
+                )
+            }
+            Error::RpcServerNotAvailable => write!(f, "virtual desktop service is not available"),
+            Error::ComNotInitialized => write!(f, "COM is not initialized"),
+            Error::ComObjectNotConnected => write!(f, "COM object is not connected"),
+            Error::ComElementNotFound => write!(f, "element not found"),
+            Error::ComError(error) => error.fmt(f),
+            Error::ComAllocatedNullPtr => write!(f, "COM call returned a null pointer"),
+            Error::InternalBorrowError => write!(f, "internal borrow error"),
+            Error::BackendNotAvailable => write!(f, "backend is not available on this platform"),
+            Error::UnsupportedBuild { build } => {
+                write!(
+                    f,
+                    "virtual desktops of Windows build {} are not supported",
+                    build
+                )
+            }
+            Error::BuildDetectionFailed(message) => {
+                write!(f, "detecting the Windows build failed: {}", message)
+            }
+            Error::NotSupported => write!(f, "operation is not supported on this Windows build"),
+            Error::ThreadSpawnFailed(message) => write!(f, "starting a thread failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_com_error_context() {
+        let error = Error::ComError(ComError::new(HRESULT(0x80004005_u32 as i32)))
+            .with_context("get_desktop_name", Some(ErrorTarget::DesktopIndex(2)))
+            .with_context("sort_desktops_by_name", None);
+        let Error::ComError(ref com_error) = error else {
+            panic!("{:?}", error);
+        };
+        assert_eq!(com_error.operation, Some("get_desktop_name"));
+        assert_eq!(com_error.target, Some(ErrorTarget::DesktopIndex(2)));
+        assert!(error
+            .to_string()
+            .starts_with("get_desktop_name of desktop 2 failed with 0x80004005"));
+
+        // Other errors are kept as they are
+        let not_found = Error::DesktopNotFound(ErrorTarget::DesktopIndex(5));
+        assert_eq!(
+            not_found.clone().with_context("switch_desktop", None),
+            not_found
+        );
+        assert_eq!(not_found.to_string(), "desktop 5 not found");
+        assert_eq!(
+            Error::WindowNotFound(HWND(0x1f2)).to_string(),
+            "window 0x1f2 not found"
+        );
+    }
+
+    #[test]
+    fn test_error_classifiers() {
+        assert!(Error::RpcServerNotAvailable.is_transient());
+        assert!(Error::ComError(ComError::new(RPC_E_CALL_REJECTED)).is_transient());
+        let not_found = Error::DesktopNotFound(ErrorTarget::DesktopIndex(5));
+        assert!(!not_found.is_transient());
+
+        assert!(not_found.is_not_found());
+        assert!(Error::ComError(ComError::new(ERROR_NOT_FOUND)).is_not_found());
+        assert!(!Error::WindowNotMovable(HWND(1)).is_not_found());
+    }
+
+    #[test]
+    fn test_error_is_std_error() {
+        let error: Box<dyn std::error::Error> = Box::new(Error::UnsupportedBuild { build: 22000 });
+        assert_eq!(
+            error.to_string(),
+            "virtual desktops of Windows build 22000 are not supported"
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use crate::Desktop;
 use crate::DesktopEventThread;
 use crate::Error;
-use windows::Win32::Foundation::HWND;
+use crate::ListenerBuilder;
+use crate::Window;
 
 #[derive(Clone)]
 pub enum DesktopEventSender<T>
@@ -15,6 +16,21 @@ where
 
     #[cfg(feature = "winit")]
     Winit(winit::event_loop::EventLoopProxy<T>),
+
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::sync::mpsc::Sender<T>),
+
+    #[cfg(feature = "tokio")]
+    TokioUnbounded(tokio::sync::mpsc::UnboundedSender<T>),
+
+    #[cfg(feature = "tokio")]
+    TokioBroadcast(tokio::sync::broadcast::Sender<T>),
+
+    #[cfg(feature = "async-channel")]
+    AsyncChannel(async_channel::Sender<T>),
+
+    #[cfg(feature = "async")]
+    Futures(futures_channel::mpsc::UnboundedSender<T>),
 }
 
 // From STD Sender
@@ -49,6 +65,61 @@ where
     }
 }
 
+// From Tokio Sender, events are dropped if the channel is full
+#[cfg(feature = "tokio")]
+impl<T> From<tokio::sync::mpsc::Sender<T>> for DesktopEventSender<T>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+{
+    fn from(sender: tokio::sync::mpsc::Sender<T>) -> Self {
+        DesktopEventSender::Tokio(sender)
+    }
+}
+
+// From Tokio UnboundedSender
+#[cfg(feature = "tokio")]
+impl<T> From<tokio::sync::mpsc::UnboundedSender<T>> for DesktopEventSender<T>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+{
+    fn from(sender: tokio::sync::mpsc::UnboundedSender<T>) -> Self {
+        DesktopEventSender::TokioUnbounded(sender)
+    }
+}
+
+// From Tokio broadcast Sender
+#[cfg(feature = "tokio")]
+impl<T> From<tokio::sync::broadcast::Sender<T>> for DesktopEventSender<T>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+{
+    fn from(sender: tokio::sync::broadcast::Sender<T>) -> Self {
+        DesktopEventSender::TokioBroadcast(sender)
+    }
+}
+
+// From async-channel Sender, events are dropped if the channel is full
+#[cfg(feature = "async-channel")]
+impl<T> From<async_channel::Sender<T>> for DesktopEventSender<T>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+{
+    fn from(sender: async_channel::Sender<T>) -> Self {
+        DesktopEventSender::AsyncChannel(sender)
+    }
+}
+
+// From futures UnboundedSender
+#[cfg(feature = "async")]
+impl<T> From<futures_channel::mpsc::UnboundedSender<T>> for DesktopEventSender<T>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+{
+    fn from(sender: futures_channel::mpsc::UnboundedSender<T>) -> Self {
+        DesktopEventSender::Futures(sender)
+    }
+}
+
 impl<T> DesktopEventSender<T> {
     pub fn try_send(&self, event: T) {
         match self {
@@ -65,6 +136,31 @@ impl<T> DesktopEventSender<T> {
             DesktopEventSender::Winit(sender) => {
                 let _ = sender.send_event(event);
             }
+
+            #[cfg(feature = "tokio")]
+            DesktopEventSender::Tokio(sender) => {
+                let _ = sender.try_send(event);
+            }
+
+            #[cfg(feature = "tokio")]
+            DesktopEventSender::TokioUnbounded(sender) => {
+                let _ = sender.send(event);
+            }
+
+            #[cfg(feature = "tokio")]
+            DesktopEventSender::TokioBroadcast(sender) => {
+                let _ = sender.send(event);
+            }
+
+            #[cfg(feature = "async-channel")]
+            DesktopEventSender::AsyncChannel(sender) => {
+                let _ = sender.try_send(event);
+            }
+
+            #[cfg(feature = "async")]
+            DesktopEventSender::Futures(sender) => {
+                let _ = sender.unbounded_send(event);
+            }
         }
     }
 }
@@ -72,6 +168,18 @@ impl<T> DesktopEventSender<T> {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum DesktopEvent {
     DesktopCreated(Desktop),
+
+    /// Removal of the desktop has started, the desktop still exists
+    DesktopDestroyBegin {
+        destroyed: Desktop,
+        fallback: Desktop,
+    },
+
+    /// Removal of the desktop failed, the desktop still exists
+    DesktopDestroyFailed {
+        destroyed: Desktop,
+        fallback: Desktop,
+    },
     DesktopDestroyed {
         destroyed: Desktop,
         fallback: Desktop,
@@ -80,6 +188,13 @@ pub enum DesktopEvent {
         new: Desktop,
         old: Desktop,
     },
+
+    /// Switch to the desktop has finished, sent after `DesktopChanged`
+    DesktopSwitched(Desktop),
+
+    /// Remote desktop session connected, desktop is the current desktop of
+    /// the session
+    RemoteDesktopConnected(Desktop),
     DesktopNameChanged(Desktop, String),
     DesktopWallpaperChanged(Desktop, String),
     DesktopMoved {
@@ -87,7 +202,231 @@ pub enum DesktopEvent {
         old_index: i64,
         new_index: i64,
     },
-    WindowChanged(HWND),
+
+    /// View of the window changed, sent for every change before the more
+    /// specific window event below
+    WindowChanged(Window),
+
+    /// Window moved from a desktop to another
+    WindowMovedToDesktop {
+        window: Window,
+        from: Desktop,
+        to: Desktop,
+    },
+
+    /// Window or its app was pinned on all desktops
+    WindowPinned {
+        window: Window,
+        from: Desktop,
+    },
+
+    /// Window or its app was unpinned, the window stays on the desktop
+    WindowUnpinned {
+        window: Window,
+        to: Desktop,
+    },
+
+    /// Window was seen for the first time, desktop is `None` if it's pinned
+    WindowAppeared {
+        window: Window,
+        desktop: Option<Desktop>,
+    },
+
+    /// Shell stopped responding, e.g. explorer.exe is restarting, events are
+    /// missed until `ShellReconnected`
+    ShellDisconnected,
+
+    /// Listener is registered again after `ShellDisconnected`, followed by
+    /// created, destroyed, renamed and changed desktop events for the changes
+    /// missed meanwhile
+    ShellReconnected,
+
+    /// Registering the listener failed, it's tried again periodically. If the
+    /// first registration fails, its later success is not reported, as no
+    /// events were sent before it.
+    ListenerRegistrationFailed {
+        error: Error,
+    },
+}
+
+/// Kinds of `DesktopEvent`s, combine with `|`
+///
+/// Events not in the mask of a listener are not created at all, e.g. the
+/// frequent `WindowChanged` events during window drags.
+///
+/// # Example
+///
+/// ```rust
+/// use winvd::{DesktopEvent, EventMask};
+///
+/// let mask = EventMask::DESKTOP_CHANGED | EventMask::RENAMED;
+/// assert!(mask.contains(EventMask::RENAMED));
+/// assert!(!mask.matches(&DesktopEvent::WindowChanged(Default::default())));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EventMask(u32);
+
+impl EventMask {
+    pub const NONE: EventMask = EventMask(0);
+
+    /// `DesktopCreated`
+    pub const DESKTOP_CREATED: EventMask = EventMask(1 << 0);
+
+    /// `DesktopDestroyBegin`, `DesktopDestroyFailed` and `DesktopDestroyed`
+    pub const DESKTOP_DESTROYED: EventMask = EventMask(1 << 1);
+
+    /// `DesktopChanged` and `DesktopSwitched`
+    pub const DESKTOP_CHANGED: EventMask = EventMask(1 << 2);
+
+    /// `DesktopNameChanged`
+    pub const RENAMED: EventMask = EventMask(1 << 3);
+
+    /// `DesktopWallpaperChanged`
+    pub const WALLPAPER_CHANGED: EventMask = EventMask(1 << 4);
+
+    /// `DesktopMoved`
+    pub const DESKTOP_MOVED: EventMask = EventMask(1 << 5);
+
+    /// `RemoteDesktopConnected`
+    pub const REMOTE_DESKTOP: EventMask = EventMask(1 << 6);
+
+    /// `WindowChanged`, sent for every change of a window's view
+    pub const WINDOW_CHANGED: EventMask = EventMask(1 << 7);
+
+    /// `WindowMovedToDesktop`
+    pub const WINDOW_MOVED: EventMask = EventMask(1 << 8);
+
+    /// `WindowPinned` and `WindowUnpinned`
+    pub const WINDOW_PINNED: EventMask = EventMask(1 << 9);
+
+    /// `WindowAppeared`
+    pub const WINDOW_APPEARED: EventMask = EventMask(1 << 10);
+
+    /// `ShellDisconnected`, `ShellReconnected` and `ListenerRegistrationFailed`
+    pub const SHELL: EventMask = EventMask(1 << 11);
+
+    /// Events about windows
+    pub const WINDOWS: EventMask = EventMask(
+        Self::WINDOW_CHANGED.0
+            | Self::WINDOW_MOVED.0
+            | Self::WINDOW_PINNED.0
+            | Self::WINDOW_APPEARED.0,
+    );
+
+    pub const ALL: EventMask = EventMask((1 << 12) - 1);
+
+    /// Kind of the event
+    pub fn of(event: &DesktopEvent) -> EventMask {
+        match event {
+            DesktopEvent::DesktopCreated(_) => Self::DESKTOP_CREATED,
+            DesktopEvent::DesktopDestroyBegin { .. }
+            | DesktopEvent::DesktopDestroyFailed { .. }
+            | DesktopEvent::DesktopDestroyed { .. } => Self::DESKTOP_DESTROYED,
+            DesktopEvent::DesktopChanged { .. } | DesktopEvent::DesktopSwitched(_) => {
+                Self::DESKTOP_CHANGED
+            }
+            DesktopEvent::RemoteDesktopConnected(_) => Self::REMOTE_DESKTOP,
+            DesktopEvent::DesktopNameChanged(..) => Self::RENAMED,
+            DesktopEvent::DesktopWallpaperChanged(..) => Self::WALLPAPER_CHANGED,
+            DesktopEvent::DesktopMoved { .. } => Self::DESKTOP_MOVED,
+            DesktopEvent::WindowChanged(_) => Self::WINDOW_CHANGED,
+            DesktopEvent::WindowMovedToDesktop { .. } => Self::WINDOW_MOVED,
+            DesktopEvent::WindowPinned { .. } | DesktopEvent::WindowUnpinned { .. } => {
+                Self::WINDOW_PINNED
+            }
+            DesktopEvent::WindowAppeared { .. } => Self::WINDOW_APPEARED,
+            DesktopEvent::ShellDisconnected
+            | DesktopEvent::ShellReconnected
+            | DesktopEvent::ListenerRegistrationFailed { .. } => Self::SHELL,
+        }
+    }
+
+    /// Are all kinds of `other` in the mask
+    pub fn contains(self, other: EventMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Is any kind of `other` in the mask
+    pub fn intersects(self, other: EventMask) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Is the kind of the event in the mask
+    pub fn matches(self, event: &DesktopEvent) -> bool {
+        self.contains(Self::of(event))
+    }
+
+    /// Kinds of both masks, same as `|` but usable in constants
+    pub const fn union(self, other: EventMask) -> EventMask {
+        EventMask(self.0 | other.0)
+    }
+}
+
+impl Default for EventMask {
+    fn default() -> Self {
+        EventMask::ALL
+    }
+}
+
+impl std::ops::BitOr for EventMask {
+    type Output = EventMask;
+
+    fn bitor(self, other: EventMask) -> EventMask {
+        self.union(other)
+    }
+}
+
+impl std::ops::BitOrAssign for EventMask {
+    fn bitor_assign(&mut self, other: EventMask) {
+        self.0 |= other.0;
+    }
+}
+
+impl std::ops::Not for EventMask {
+    type Output = EventMask;
+
+    fn not(self) -> EventMask {
+        EventMask(!self.0 & EventMask::ALL.0)
+    }
+}
+
+impl DesktopEvent {
+    /// Desktop the event is about, e.g. the created, destroyed or the new
+    /// current desktop, or the desktop a window moved to
+    pub fn desktop(&self) -> Option<Desktop> {
+        match self {
+            DesktopEvent::DesktopCreated(desktop)
+            | DesktopEvent::DesktopSwitched(desktop)
+            | DesktopEvent::RemoteDesktopConnected(desktop)
+            | DesktopEvent::DesktopNameChanged(desktop, _)
+            | DesktopEvent::DesktopWallpaperChanged(desktop, _)
+            | DesktopEvent::DesktopMoved { desktop, .. }
+            | DesktopEvent::WindowUnpinned { to: desktop, .. }
+            | DesktopEvent::WindowMovedToDesktop { to: desktop, .. }
+            | DesktopEvent::WindowPinned { from: desktop, .. } => Some(*desktop),
+            DesktopEvent::DesktopDestroyBegin { destroyed, .. }
+            | DesktopEvent::DesktopDestroyFailed { destroyed, .. }
+            | DesktopEvent::DesktopDestroyed { destroyed, .. } => Some(*destroyed),
+            DesktopEvent::DesktopChanged { new, .. } => Some(*new),
+            DesktopEvent::WindowAppeared { desktop, .. } => *desktop,
+            DesktopEvent::WindowChanged(_)
+            | DesktopEvent::ShellDisconnected
+            | DesktopEvent::ShellReconnected
+            | DesktopEvent::ListenerRegistrationFailed { .. } => None,
+        }
+    }
+
+    /// Window the event is about
+    pub fn window(&self) -> Option<Window> {
+        match self {
+            DesktopEvent::WindowChanged(window)
+            | DesktopEvent::WindowMovedToDesktop { window, .. }
+            | DesktopEvent::WindowPinned { window, .. }
+            | DesktopEvent::WindowUnpinned { window, .. }
+            | DesktopEvent::WindowAppeared { window, .. } => Some(*window),
+            _ => None,
+        }
+    }
 }
 
 /// Create event sending thread, give this `crossbeam_channel::Sender<T>`,
@@ -100,7 +439,8 @@ pub enum DesktopEvent {
 ///
 /// # Example
 ///
-/// ```rust
+/// ```rust,no_run
+/// # use winvd::*;
 /// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
 /// let _notifications_thread = listen_desktop_events(tx);
 /// // Do with receiver something
@@ -110,8 +450,11 @@ pub enum DesktopEvent {
 /// // When `_notifications_thread` is dropped the thread is joined and listener closed.
 /// ```
 ///
-/// Additionally you can pass crossbeam-channel sender, or winit eventloop proxy
-/// to the function.
+/// Additionally you can pass crossbeam-channel sender, winit eventloop proxy,
+/// tokio mpsc or broadcast sender, or async-channel sender to the function.
+///
+/// The thread runs with the defaults of `ListenerBuilder`, use it to change
+/// e.g. the thread priority or to filter the events.
 ///
 pub fn listen_desktop_events<T, S>(sender: S) -> Result<DesktopEventThread, Error>
 where
@@ -120,3 +463,120 @@ where
 {
     DesktopEventThread::new(sender.into())
 }
+
+/// Create event sending thread, which sends only the kinds of events in the
+/// mask
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+/// let _thread =
+///     listen_desktop_events_filtered(tx, EventMask::DESKTOP_CHANGED | EventMask::RENAMED);
+/// ```
+///
+/// For other conditions give a predicate to `ListenerBuilder::filter`, e.g.
+/// to get the events of one desktop:
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+/// let desktop = get_desktop(1).get_id().unwrap();
+/// let _thread = ListenerBuilder::new()
+///     .mask(!EventMask::WINDOW_CHANGED)
+///     .filter(move |event| event.desktop() == Some(desktop.into()))
+///     .spawn(tx);
+/// ```
+pub fn listen_desktop_events_filtered<T, S>(
+    sender: S,
+    mask: EventMask,
+) -> Result<DesktopEventThread, Error>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+    S: Into<DesktopEventSender<T>> + Clone,
+{
+    ListenerBuilder::new().mask(mask).spawn(sender)
+}
+
+/// Stream of desktop events, create with `listen_desktop_events_stream()`.
+///
+/// The listener is closed and the thread joined when the stream is dropped.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct DesktopEventStream {
+    receiver: futures_channel::mpsc::UnboundedReceiver<DesktopEvent>,
+    _thread: DesktopEventThread,
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for DesktopEventStream {
+    type Item = DesktopEvent;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::pin::Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// Create event listener thread, and return the events as a stream
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// use futures::StreamExt;
+///
+/// # async fn run() -> Result<()> {
+/// let mut events = listen_desktop_events_stream()?;
+/// while let Some(event) = events.next().await {
+///     println!("{:?}", event);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub fn listen_desktop_events_stream() -> Result<DesktopEventStream, Error> {
+    let (tx, rx) = futures_channel::mpsc::unbounded::<DesktopEvent>();
+    Ok(DesktopEventStream {
+        receiver: rx,
+        _thread: DesktopEventThread::new(tx.into())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::Foundation::HWND;
+
+    #[test]
+    fn test_event_mask() {
+        let mask = EventMask::DESKTOP_CHANGED | EventMask::RENAMED;
+        assert!(mask.contains(EventMask::RENAMED));
+        assert!(!mask.contains(EventMask::RENAMED | EventMask::WINDOW_MOVED));
+        assert!(mask.intersects(EventMask::RENAMED | EventMask::WINDOW_MOVED));
+
+        let desktop = Desktop::from(1);
+        assert!(mask.matches(&DesktopEvent::DesktopSwitched(desktop)));
+        assert!(!mask.matches(&DesktopEvent::WindowChanged(HWND(1).into())));
+        assert!((!EventMask::WINDOW_CHANGED).matches(&DesktopEvent::ShellReconnected));
+        assert_eq!(!EventMask::ALL, EventMask::NONE);
+        assert_eq!(EventMask::default(), EventMask::ALL);
+    }
+
+    #[test]
+    fn test_event_desktop_and_window() {
+        let (desktop, window) = (Desktop::from(1), Window::from(HWND(2)));
+        let event = DesktopEvent::WindowMovedToDesktop {
+            window,
+            from: Desktop::from(0),
+            to: desktop,
+        };
+        assert_eq!(event.desktop(), Some(desktop));
+        assert_eq!(event.window(), Some(window));
+        assert_eq!(DesktopEvent::WindowChanged(window).desktop(), None);
+        assert_eq!(DesktopEvent::DesktopCreated(desktop).window(), None);
+    }
+}
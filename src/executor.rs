@@ -0,0 +1,457 @@
+/// Dedicated COM thread, an opt-in alternative to the thread local COM objects
+///
+/// By default every thread calling this crate creates its own COM objects, see
+/// `with_com_objects`. With `Backend::ComThread` all calls are instead sent to
+/// one long-lived thread which owns the COM objects, initializes COM
+/// explicitly and pumps window messages between the calls.
+use super::*;
+use std::cell::RefCell;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{JoinHandle, ThreadId};
+
+#[cfg(windows)]
+use crate::comobjects::ComBackend;
+#[cfg(windows)]
+use std::sync::mpsc::TryRecvError;
+#[cfg(windows)]
+use windows::Win32::Foundation::{CloseHandle, HANDLE, HWND, WAIT_FAILED};
+#[cfg(windows)]
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED};
+#[cfg(windows)]
+use windows::Win32::System::Threading::{CreateEventW, SetEvent, INFINITE};
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{
+    DispatchMessageW, MsgWaitForMultipleObjectsEx, PeekMessageW, TranslateMessage, MSG,
+    MWMO_INPUTAVAILABLE, PM_REMOVE, QS_ALLINPUT, WM_QUIT,
+};
+
+type Job<'a> = Box<dyn FnOnce(&dyn VirtualDesktopBackend) + Send + 'a>;
+
+/// Jobs received by the thread, and on Windows the event set when they are
+/// queued
+struct JobQueue {
+    jobs: Receiver<Job<'static>>,
+    #[cfg(windows)]
+    event: HANDLE,
+}
+
+/// Backend owned by the COM thread
+enum ThreadBackend {
+    #[cfg(windows)]
+    Com(ComBackend),
+    Simulated(Arc<SimulatedShell>),
+}
+
+impl ThreadBackend {
+    fn get(&self) -> &dyn VirtualDesktopBackend {
+        match self {
+            #[cfg(windows)]
+            ThreadBackend::Com(objects) => objects.get(),
+            ThreadBackend::Simulated(shell) => shell.as_ref(),
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_BACKEND: RefCell<Option<ThreadBackend>> = const { RefCell::new(None) };
+}
+
+/// Long-lived thread which owns the COM objects and runs the calls in order
+///
+/// Select it with `set_backend(Backend::ComThread(..))`, after that `Desktop`
+/// and all functions of this crate can be used from any thread. The thread is
+/// stopped when the last reference is dropped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let thread = std::sync::Arc::new(ComThread::start().unwrap());
+/// set_backend(Backend::ComThread(thread.clone()));
+///
+/// // Closures given to `run` may borrow
+/// let names = ["Mail", "Code"];
+/// thread
+///     .run(|backend| {
+///         for (index, name) in names.iter().enumerate() {
+///             backend.set_desktop_name(&get_desktop(index as u32), name)?;
+///         }
+///         Ok(())
+///     })
+///     .unwrap();
+/// ```
+pub struct ComThread {
+    jobs: Option<Sender<Job<'static>>>,
+    thread: Option<JoinHandle<()>>,
+    thread_id: ThreadId,
+
+    /// Auto-reset event the thread waits on besides its messages, set when
+    /// jobs are queued. Unlike a thread message it is not lost if a modal
+    /// loop dispatches the messages.
+    #[cfg(windows)]
+    event: HANDLE,
+
+    /// Backend of the thread, listeners create their own instance of it
+    backend: Backend,
+}
+
+impl ComThread {
+    /// Start the thread, COM is initialized as single-threaded apartment
+    ///
+    /// Fails if COM can not be initialized, or with `BackendNotAvailable` if
+    /// not on Windows.
+    pub fn start() -> Result<Self> {
+        Self::start_with(Backend::Com)
+    }
+
+    /// Start the thread with the given backend, used by tests with the
+    /// simulated shell
+    pub(crate) fn start_with(backend: Backend) -> Result<Self> {
+        let (thread, ready) = Self::spawn(backend)?;
+
+        // Wait until the thread is ready to run jobs
+        match ready.recv().unwrap_or(Err(Error::BackendNotAvailable)) {
+            Ok(()) => Ok(thread),
+            Err(er) => Err(er),
+        }
+    }
+
+    /// Start the thread without waiting for it, used by the async functions
+    /// so that the runtime is not blocked
+    ///
+    /// Jobs queued before the thread is ready are run once it is. If it fails
+    /// to start they are dropped, and the thread is finished.
+    #[cfg(feature = "async")]
+    pub(crate) fn start_in_background(backend: Backend) -> Result<Self> {
+        Self::spawn(backend).map(|(thread, _)| thread)
+    }
+
+    fn spawn(backend: Backend) -> Result<(Self, Receiver<Result<()>>)> {
+        let backend = match backend {
+            Backend::ComThread(thread) => thread.backend.clone(),
+            backend => backend,
+        };
+        #[cfg(windows)]
+        let event = unsafe { CreateEventW(None, false, false, None)? };
+
+        let (jobs_tx, jobs_rx) = channel::<Job<'static>>();
+        let (ready_tx, ready_rx) = channel::<Result<()>>();
+        let queue = JobQueue {
+            jobs: jobs_rx,
+            #[cfg(windows)]
+            event,
+        };
+        let thread_backend = backend.clone();
+        let thread = std::thread::Builder::new()
+            .name("winvd-com".to_owned())
+            .spawn(move || run_thread(thread_backend, queue, ready_tx));
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(er) => {
+                #[cfg(windows)]
+                let _ = unsafe { CloseHandle(event) };
+                return Err(Error::ThreadSpawnFailed(er.to_string()));
+            }
+        };
+        let thread_id = thread.thread().id();
+
+        // If the thread fails to start, it's joined and the event closed when
+        // this is dropped
+        let com_thread = ComThread {
+            jobs: Some(jobs_tx),
+            thread: Some(thread),
+            thread_id,
+            #[cfg(windows)]
+            event,
+            backend,
+        };
+        Ok((com_thread, ready_rx))
+    }
+
+    /// Run the function on the thread and wait for the result
+    ///
+    /// The function may borrow from the caller. If it panics, the panic is
+    /// resumed on the calling thread. Calls made from the thread itself, e.g.
+    /// inside `run`, are run directly.
+    pub fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&dyn VirtualDesktopBackend) -> Result<T> + Send,
+        T: Send,
+    {
+        if std::thread::current().id() == self.thread_id {
+            return THREAD_BACKEND.with(|backend| match backend.borrow().as_ref() {
+                Some(backend) => f(backend.get()),
+                None => Err(Error::BackendNotAvailable),
+            });
+        }
+
+        let (tx, rx) = channel::<std::thread::Result<Result<T>>>();
+        let job: Job<'_> = Box::new(move |backend| {
+            let _ = tx.send(catch_unwind(AssertUnwindSafe(|| f(backend))));
+        });
+
+        // Safety: the job is given a static lifetime so it can be queued, it
+        // is run or dropped before this function returns because either it is
+        // taken back, or the result is waited until the sender inside the job
+        // is gone
+        let job: Job<'static> = unsafe { std::mem::transmute(job) };
+        let slot = Arc::new(Mutex::new(Some(job)));
+        let queued = Arc::clone(&slot);
+        let queued: Job<'static> = Box::new(move |backend| {
+            let job = lock(&queued).take();
+            if let Some(job) = job {
+                job(backend);
+            }
+        });
+        if let Err(er) = self.queue(queued) {
+            // The thread was not woken, the job is taken back unless the thread
+            // already started it
+            if lock(&slot).take().is_some() {
+                return Err(er);
+            }
+        }
+
+        match rx.recv() {
+            Ok(Ok(result)) => result,
+            Ok(Err(panic)) => resume_unwind(panic),
+
+            // The thread stopped without running the job
+            Err(_) => Err(Error::BackendNotAvailable),
+        }
+    }
+
+    /// Queue the function to run on the thread without waiting for it, used
+    /// by the async functions
+    ///
+    /// The function is dropped without running if the thread stops first.
+    #[cfg(feature = "async")]
+    pub(crate) fn post<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&dyn VirtualDesktopBackend) + Send + 'static,
+    {
+        self.queue(Box::new(f))
+    }
+
+    /// Fails if the thread has stopped, or if it could not be woken, in which
+    /// case the job stays queued until the thread wakes up for other reasons
+    fn queue(&self, job: Job<'static>) -> Result<()> {
+        let jobs = self.jobs.as_ref().ok_or(Error::BackendNotAvailable)?;
+        if jobs.send(job).is_err() {
+            return Err(Error::BackendNotAvailable);
+        }
+        self.wake()
+    }
+
+    /// Has the thread stopped, e.g. because it failed to start
+    #[cfg(feature = "async")]
+    pub(crate) fn is_finished(&self) -> bool {
+        match &self.thread {
+            Some(thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Is the backend of the thread usable on this platform
+    pub(crate) fn is_available(&self) -> bool {
+        self.backend.is_available()
+    }
+
+    /// Backend for threads that need their own instance, e.g. listeners
+    pub(crate) fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    #[cfg(windows)]
+    fn wake(&self) -> Result<()> {
+        unsafe { SetEvent(self.event)? };
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    fn wake(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ComThread {
+    fn drop(&mut self) {
+        // The thread stops once it sees the queue is closed
+        self.jobs.take();
+        let _ = self.wake();
+
+        // The last reference may be dropped by a job on the thread itself, it
+        // checks the queue before waiting on the event again
+        if std::thread::current().id() != self.thread_id {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+        #[cfg(windows)]
+        let _ = unsafe { CloseHandle(self.event) };
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn run_thread(backend: Backend, queue: JobQueue, ready: Sender<Result<()>>) {
+    let thread_backend = match backend {
+        #[cfg(windows)]
+        Backend::Com => match unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) } {
+            Ok(()) => ThreadBackend::Com(ComBackend::new()),
+            Err(er) => {
+                let _ = ready.send(Err(er.into()));
+                return;
+            }
+        },
+
+        #[cfg(not(windows))]
+        Backend::Com => {
+            let _ = ready.send(Err(Error::BackendNotAvailable));
+            return;
+        }
+
+        Backend::Simulated(shell) => ThreadBackend::Simulated(shell),
+
+        // Nested threads are resolved in `start_with`
+        Backend::ComThread(_) => {
+            let _ = ready.send(Err(Error::BackendNotAvailable));
+            return;
+        }
+    };
+    THREAD_BACKEND.with(|b| *b.borrow_mut() = Some(thread_backend));
+
+    let _ = ready.send(Ok(()));
+    pump(&queue);
+
+    // COM objects are released before COM is uninitialized
+    let thread_backend = THREAD_BACKEND.with(|b| b.borrow_mut().take());
+    #[cfg(windows)]
+    if let Some(ThreadBackend::Com(objects)) = thread_backend {
+        drop(objects);
+        unsafe { CoUninitialize() };
+    }
+    #[cfg(not(windows))]
+    drop(thread_backend);
+}
+
+/// Runs the queued jobs and dispatches window messages until the `ComThread`
+/// is dropped or `WM_QUIT`
+///
+/// The queue is drained on every wake up, also by messages, so jobs whose
+/// event was consumed while a job ran are not left waiting.
+#[cfg(windows)]
+fn pump(queue: &JobQueue) {
+    let mut msg = MSG::default();
+    loop {
+        loop {
+            match queue.jobs.try_recv() {
+                Ok(job) => run_job(job),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        while unsafe { PeekMessageW(&mut msg, HWND::default(), 0, 0, PM_REMOVE) }.as_bool() {
+            if msg.message == WM_QUIT {
+                return;
+            }
+            unsafe {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        let wait = unsafe {
+            MsgWaitForMultipleObjectsEx(
+                Some(&[queue.event]),
+                INFINITE,
+                QS_ALLINPUT,
+                MWMO_INPUTAVAILABLE,
+            )
+        };
+        if wait == WAIT_FAILED {
+            return;
+        }
+    }
+}
+
+/// Runs the queued jobs until the `ComThread` is dropped
+#[cfg(not(windows))]
+fn pump(queue: &JobQueue) {
+    queue.jobs.iter().for_each(run_job);
+}
+
+/// Runs the function with the COM objects of the `ComThread` it's called on,
+/// `None` on other threads
+///
+/// Functions of this crate called inside jobs, e.g. by the async functions,
+/// use these instead of creating thread local COM objects.
+#[cfg(windows)]
+pub(crate) fn with_com_thread_objects<F, T>(f: F) -> Option<T>
+where
+    F: FnOnce(&dyn VirtualDesktopBackend) -> T,
+{
+    THREAD_BACKEND.with(|backend| match backend.borrow().as_ref() {
+        Some(ThreadBackend::Com(objects)) => Some(f(objects.get())),
+        _ => None,
+    })
+}
+
+fn run_job(job: Job<'static>) {
+    THREAD_BACKEND.with(|backend| {
+        if let Some(backend) = backend.borrow().as_ref() {
+            job(backend.get());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_com_thread_runs_borrowing_closures() {
+        let shell = Arc::new(SimulatedShell::with_desktops(2));
+        let thread = ComThread::start_with(Backend::Simulated(shell.clone())).unwrap();
+
+        let names = ["Mail".to_owned(), "Code".to_owned()];
+        let caller = std::thread::current().id();
+        let on_thread = thread
+            .run(|backend| {
+                for (index, name) in names.iter().enumerate() {
+                    backend.set_desktop_name(&Desktop::from(index as u32), name)?;
+                }
+                Ok(std::thread::current().id())
+            })
+            .unwrap();
+        assert_ne!(on_thread, caller);
+        assert_eq!(shell.get_desktop_name(&1.into()).unwrap(), "Code");
+
+        // Nested calls are run directly on the thread
+        let count = thread
+            .run(|_| thread.run(|backend| backend.get_desktop_count()))
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // Errors are returned and panics resumed on the caller
+        assert_eq!(
+            thread
+                .run(|backend| backend.get_desktop_name(&5.into()))
+                .unwrap_err(),
+            Error::DesktopNotFound(ErrorTarget::DesktopIndex(5))
+        );
+        let panic = catch_unwind(AssertUnwindSafe(|| {
+            thread.run(|_| -> Result<()> { panic!("in job") })
+        }));
+        assert!(panic.is_err());
+        assert_eq!(thread.run(|backend| backend.get_desktop_count()), Ok(2));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_com_thread_not_available() {
+        assert_eq!(ComThread::start().err(), Some(Error::BackendNotAvailable));
+    }
+}
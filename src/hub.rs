@@ -0,0 +1,190 @@
+/// Process wide event hub sharing one listener between subscribers
+///
+/// Each `listen_desktop_events` call registers its own notification with
+/// explorer.exe, and every registration delays the shell. The hub registers
+/// once for all subscribers and all kinds of events, the events are filtered
+/// and sent to the subscribers from a separate dispatcher thread so that slow
+/// senders do not delay the shell. The registration is removed when the last
+/// subscriber leaves.
+use super::*;
+use std::collections::BTreeMap;
+use std::sync::mpsc::channel;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+type SubscriberSender = Box<dyn Fn(DesktopEvent) + Send>;
+
+struct Subscribers {
+    next_id: u64,
+    senders: BTreeMap<u64, (EventMask, SubscriberSender)>,
+}
+
+/// Shared listener, registered with all kinds of events
+struct Hub {
+    listener: DesktopEventThread,
+    dispatcher: Option<JoinHandle<()>>,
+}
+
+// Lock order is HUB before SUBSCRIBERS, the dispatcher locks only SUBSCRIBERS
+static HUB: Mutex<Option<Hub>> = Mutex::new(None);
+static SUBSCRIBERS: Mutex<Subscribers> = Mutex::new(Subscribers {
+    next_id: 1,
+    senders: BTreeMap::new(),
+});
+
+// Options of the listener set with `set_event_hub_listener`
+static LISTENER: Mutex<Option<ListenerBuilder>> = Mutex::new(None);
+
+impl Hub {
+    fn start() -> Result<Hub> {
+        let builder = lock(&LISTENER)
+            .clone()
+            .unwrap_or_else(|| ListenerBuilder::new().name("winvd-event-hub"));
+        let (tx, rx) = channel::<DesktopEvent>();
+        let listener = builder.mask(EventMask::ALL).spawn(tx)?;
+        let dispatcher = std::thread::Builder::new()
+            .name("winvd-event-hub-dispatcher".to_owned())
+            .spawn(move || {
+                // Ends when the listener is stopped and the sender dropped
+                for event in rx {
+                    let kind = EventMask::of(&event);
+                    let subscribers = lock(&SUBSCRIBERS);
+                    for (mask, sender) in subscribers.senders.values() {
+                        if mask.contains(kind) {
+                            sender(event.clone());
+                        }
+                    }
+                }
+            })
+            .map_err(|e| Error::ThreadSpawnFailed(e.to_string()))?;
+        Ok(Hub {
+            listener,
+            dispatcher: Some(dispatcher),
+        })
+    }
+}
+
+impl Drop for Hub {
+    fn drop(&mut self) {
+        let _ = self.listener.stop();
+        if let Some(dispatcher) = self.dispatcher.take() {
+            let _ = dispatcher.join();
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Subscription to the shared event hub, create with
+/// `subscribe_desktop_events(sender)`
+///
+/// The events are sent until the value is dropped or `unsubscribe` is called.
+#[derive(Debug)]
+pub struct Subscription {
+    id: u64,
+}
+
+impl Subscription {
+    /// Stop sending the events, same as dropping the subscription
+    pub fn unsubscribe(self) {}
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut hub = lock(&HUB);
+        let is_last = {
+            let mut subscribers = lock(&SUBSCRIBERS);
+            subscribers.senders.remove(&self.id);
+            subscribers.senders.is_empty()
+        };
+
+        // Hub is dropped while HUB is locked, so that a new subscriber waits
+        // until the old registration is removed
+        if is_last {
+            drop(hub.take());
+        }
+    }
+}
+
+/// Subscribe to the desktop events of the shared event hub
+///
+/// Takes the same senders as `listen_desktop_events`. The first subscriber
+/// starts the listener, later subscribers share it.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+/// let subscription = subscribe_desktop_events(tx).unwrap();
+/// for event in rx.iter().take(10) {
+///     println!("{:?}", event);
+/// }
+/// subscription.unsubscribe();
+/// ```
+pub fn subscribe_desktop_events<T, S>(sender: S) -> Result<Subscription>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+    S: Into<DesktopEventSender<T>>,
+{
+    subscribe_desktop_events_filtered(sender, EventMask::ALL)
+}
+
+/// Subscribe to the kinds of events in the mask from the shared event hub
+///
+/// The hub listens to all kinds of events, the mask only selects the ones sent
+/// to this subscriber.
+pub fn subscribe_desktop_events_filtered<T, S>(sender: S, mask: EventMask) -> Result<Subscription>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+    S: Into<DesktopEventSender<T>>,
+{
+    let sender: DesktopEventSender<T> = sender.into();
+    let mut hub = lock(&HUB);
+    if hub.is_none() {
+        *hub = Some(Hub::start()?);
+    }
+
+    let mut subscribers = lock(&SUBSCRIBERS);
+    let id = subscribers.next_id;
+    subscribers.next_id += 1;
+    subscribers.senders.insert(
+        id,
+        (
+            mask,
+            Box::new(move |event: DesktopEvent| sender.try_send(event.into())),
+        ),
+    );
+    Ok(Subscription { id })
+}
+
+/// Set the options of the shared listener, e.g. its priority or interval
+///
+/// The options are used the next time the hub starts, i.e. when the first
+/// subscriber subscribes, a running hub keeps its listener. The mask of the
+/// builder is ignored, as the hub listens to all kinds of events, and a
+/// filter applies to all subscribers.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// set_event_hub_listener(
+///     ListenerBuilder::new()
+///         .name("desktop-events")
+///         .priority(ThreadPriority::Normal),
+/// );
+/// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+/// let _subscription = subscribe_desktop_events(tx).unwrap();
+/// ```
+pub fn set_event_hub_listener(builder: ListenerBuilder) {
+    *lock(&LISTENER) = Some(builder);
+}
+
+/// Is the shared listener running
+#[cfg(all(test, not(feature = "integration-tests")))]
+pub(crate) fn is_hub_running() -> bool {
+    lock(&HUB).is_some()
+}
@@ -39,9 +39,14 @@ use std::ffi::c_void;
 use std::ops::Deref;
 use windows::{
     core::{ComInterface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, HSTRING},
-    Win32::{Foundation::HWND, UI::Shell::Common::IObjectArray},
+    Win32::{
+        Foundation::{HWND, RECT},
+        UI::Shell::Common::IObjectArray,
+    },
 };
 
+pub mod windows10;
+
 /// ComIn is a wrapper for COM objects that are passed as input parameters. It
 /// allows to keep the life of the COM object for the duration of the function
 /// call.
@@ -158,14 +163,6 @@ type APPLICATION_VIEW_COMPATIBILITY_POLICY = UINT;
 #[allow(non_camel_case_types)]
 type APPLICATION_VIEW_CLOAK_TYPE = UINT;
 
-#[allow(dead_code)]
-pub struct RECT {
-    left: LONG,
-    top: LONG,
-    right: LONG,
-    bottom: LONG,
-}
-
 #[allow(dead_code)]
 pub struct SIZE {
     cx: LONG,
@@ -324,14 +321,14 @@ pub unsafe trait IVirtualDesktop: IUnknown {
 
 #[windows_interface::interface("1841c6d7-4f9d-42c0-af41-8747538f10e5")]
 pub unsafe trait IApplicationViewCollection: IUnknown {
-    pub unsafe fn get_views(&self, out_views: *mut IObjectArray) -> HRESULT;
+    pub unsafe fn get_views(&self, out_views: *mut Option<IObjectArray>) -> HRESULT;
 
-    pub unsafe fn get_views_by_zorder(&self, out_views: *mut IObjectArray) -> HRESULT;
+    pub unsafe fn get_views_by_zorder(&self, out_views: *mut Option<IObjectArray>) -> HRESULT;
 
     pub unsafe fn get_views_by_app_user_model_id(
         &self,
         id: PCWSTR,
-        out_views: *mut IObjectArray,
+        out_views: *mut Option<IObjectArray>,
     ) -> HRESULT;
 
     pub unsafe fn get_view_for_hwnd(
@@ -352,11 +349,11 @@ pub unsafe trait IApplicationViewCollection: IUnknown {
         out_view: *mut IApplicationView,
     ) -> HRESULT;
 
-    pub unsafe fn get_view_in_focus(&self, out_view: *mut IApplicationView) -> HRESULT;
+    pub unsafe fn get_view_in_focus(&self, out_view: *mut Option<IApplicationView>) -> HRESULT;
 
     pub unsafe fn try_get_last_active_visible_view(
         &self,
-        out_view: *mut IApplicationView,
+        out_view: *mut Option<IApplicationView>,
     ) -> HRESULT;
 
     pub unsafe fn refresh_collection(&self) -> HRESULT;
@@ -427,6 +424,71 @@ pub unsafe trait IVirtualDesktopNotification: IUnknown {
     ) -> HRESULT;
 }
 
+/// Notification of the Windows 11 builds with per monitor desktops, the
+/// desktop notifications take the monitors as the first parameter. The
+/// desktops of these builds have the id at the same place, so they are passed
+/// as `IVirtualDesktop` to read the id only.
+#[windows_interface::interface("CD403E52-DEED-4C13-B437-B98380F2B1E8")]
+pub unsafe trait IVirtualDesktopNotificationMonitors: IUnknown {
+    pub unsafe fn virtual_desktop_created(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_destroy_begin(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_destroy_failed(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_destroyed(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_is_per_monitor_changed(&self, is_per_monitor: BOOL) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_moved(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop: ComIn<IVirtualDesktop>,
+        old_index: i64,
+        new_index: i64,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_name_changed(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+        name: HSTRING,
+    ) -> HRESULT;
+
+    pub unsafe fn view_virtual_desktop_changed(&self, view: ComIn<IApplicationView>) -> HRESULT;
+
+    pub unsafe fn current_virtual_desktop_changed(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_old: ComIn<IVirtualDesktop>,
+        desktop_new: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_wallpaper_changed(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+        name: HSTRING,
+    ) -> HRESULT;
+}
+
 #[windows_interface::interface("0CD45E71-D927-4F15-8B0A-8FEF525337BF")]
 pub unsafe trait IVirtualDesktopNotificationService: IUnknown {
     pub unsafe fn register(
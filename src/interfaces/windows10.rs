@@ -0,0 +1,172 @@
+/// Interface definitions for the Virtual Desktop API of Windows 10, builds
+/// 19041 to 19045
+///
+/// Only the interfaces that differ from Windows 11 are here, the others, e.g.
+/// `IApplicationView` and `IVirtualDesktopPinnedApps`, are shared. The same
+/// lifetime rules apply, see `interfaces.rs`.
+use super::{ComIn, IApplicationView};
+use windows::{
+    core::{IUnknown, IUnknown_Vtbl, GUID, HRESULT, HSTRING},
+    Win32::UI::Shell::Common::IObjectArray,
+};
+
+type UINT = u32;
+
+#[windows_interface::interface("FF72FFDD-BE7E-43FC-9C03-AD81681E88E4")]
+pub unsafe trait IVirtualDesktop: IUnknown {
+    pub unsafe fn is_view_visible(
+        &self,
+        p_view: ComIn<IApplicationView>,
+        out_bool: *mut u32,
+    ) -> HRESULT;
+    pub unsafe fn get_id(&self, out_guid: *mut GUID) -> HRESULT;
+}
+
+/// Desktop with a name, queried from `IVirtualDesktop`
+#[windows_interface::interface("31EBDE3F-6EC3-4CBD-B9FB-0EF6D09B41F4")]
+pub unsafe trait IVirtualDesktop2: IUnknown {
+    pub unsafe fn is_view_visible(
+        &self,
+        p_view: ComIn<IApplicationView>,
+        out_bool: *mut u32,
+    ) -> HRESULT;
+    pub unsafe fn get_id(&self, out_guid: *mut GUID) -> HRESULT;
+    pub unsafe fn get_name(&self, out_string: *mut HSTRING) -> HRESULT;
+}
+
+/// Unlike on Windows 11 the methods have no monitors parameter and there is
+/// no notification of moved, renamed or switched desktops
+#[windows_interface::interface("C179334C-4295-40D3-BEA1-C654D965605A")]
+pub unsafe trait IVirtualDesktopNotification: IUnknown {
+    pub unsafe fn virtual_desktop_created(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_destroy_begin(
+        &self,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_destroy_failed(
+        &self,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn virtual_desktop_destroyed(
+        &self,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn view_virtual_desktop_changed(&self, view: ComIn<IApplicationView>) -> HRESULT;
+
+    pub unsafe fn current_virtual_desktop_changed(
+        &self,
+        desktop_old: ComIn<IVirtualDesktop>,
+        desktop_new: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+}
+
+#[windows_interface::interface("F31574D6-B682-4CDC-BD56-1827860ABEC6")]
+pub unsafe trait IVirtualDesktopManagerInternal: IUnknown {
+    pub unsafe fn get_desktop_count(&self, out_count: *mut UINT) -> HRESULT;
+
+    pub unsafe fn move_view_to_desktop(
+        &self,
+        view: ComIn<IApplicationView>,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn can_move_view_between_desktops(
+        &self,
+        view: ComIn<IApplicationView>,
+        can_move: *mut i32,
+    ) -> HRESULT;
+
+    pub unsafe fn get_current_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn get_desktops(&self, out_desktops: *mut Option<IObjectArray>) -> HRESULT;
+
+    /// Get next or previous desktop
+    ///
+    /// Direction values:
+    /// 3 = Left direction
+    /// 4 = Right direction
+    pub unsafe fn get_adjacent_desktop(
+        &self,
+        in_desktop: ComIn<IVirtualDesktop>,
+        direction: UINT,
+        out_pp_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn switch_desktop(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn create_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn remove_desktop(
+        &self,
+        destroy_desktop: ComIn<IVirtualDesktop>,
+        fallback_desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn find_desktop(
+        &self,
+        guid: *const GUID,
+        out_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+}
+
+/// Manager that can rename desktops, queried from the same service as
+/// `IVirtualDesktopManagerInternal`
+#[windows_interface::interface("0F3A72B0-4566-487E-9A33-4ED302F6D6CE")]
+pub unsafe trait IVirtualDesktopManagerInternal2: IUnknown {
+    pub unsafe fn get_desktop_count(&self, out_count: *mut UINT) -> HRESULT;
+
+    pub unsafe fn move_view_to_desktop(
+        &self,
+        view: ComIn<IApplicationView>,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn can_move_view_between_desktops(
+        &self,
+        view: ComIn<IApplicationView>,
+        can_move: *mut i32,
+    ) -> HRESULT;
+
+    pub unsafe fn get_current_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn get_desktops(&self, out_desktops: *mut Option<IObjectArray>) -> HRESULT;
+
+    pub unsafe fn get_adjacent_desktop(
+        &self,
+        in_desktop: ComIn<IVirtualDesktop>,
+        direction: UINT,
+        out_pp_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn switch_desktop(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn create_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn remove_desktop(
+        &self,
+        destroy_desktop: ComIn<IVirtualDesktop>,
+        fallback_desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn find_desktop(
+        &self,
+        guid: *const GUID,
+        out_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn get_desktop_switch_include_exclude_views(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+        out_pp_desktops1: *mut IObjectArray,
+        out_pp_desktops2: *mut IObjectArray,
+    ) -> HRESULT;
+
+    pub unsafe fn set_name(&self, desktop: ComIn<IVirtualDesktop>, name: HSTRING) -> HRESULT;
+}
@@ -0,0 +1,435 @@
+/// Capturing and restoring the desktops and the placement of windows
+///
+/// Windows are identified by the app ID and the title, handles do not survive
+/// reboots. Enable with the `layout` feature.
+use super::*;
+use serde::{Deserialize, Serialize};
+use windows::core::GUID;
+
+/// Desktops, pins and windows, see `Layout::capture` and `Layout::apply`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    /// Desktops in order
+    pub desktops: Vec<DesktopLayout>,
+
+    /// Pinned apps by application user model ID
+    #[serde(default)]
+    pub pinned_apps: Vec<String>,
+
+    /// Pinned windows, shown on all desktops
+    #[serde(default)]
+    pub pinned_windows: Vec<WindowLayout>,
+
+    /// Windows and the index of their desktop
+    #[serde(default)]
+    pub windows: Vec<WindowLayout>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DesktopLayout {
+    #[serde(with = "guid_string")]
+    pub id: GUID,
+
+    #[serde(default)]
+    pub name: String,
+
+    #[serde(default)]
+    pub wallpaper: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowLayout {
+    pub app_id: String,
+
+    #[serde(default)]
+    pub title: String,
+
+    /// Index of the desktop in `Layout::desktops`, `None` for pinned windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desktop: Option<u32>,
+}
+
+impl Layout {
+    /// Capture the current desktops, pins and windows
+    ///
+    /// Pinned apps are found through their windows, apps pinned without open
+    /// windows are not captured.
+    pub fn capture() -> Result<Layout> {
+        with_com_objects(|o| Layout::capture_with(o))
+    }
+
+    /// Restore the layout
+    ///
+    /// Desktops are matched by GUID and then by name, missing desktops are
+    /// created. Desktops are renamed and reordered, extra desktops are kept at
+    /// the end. Windows are matched by app ID and title and moved back to
+    /// their desktops, windows that can not be moved are skipped.
+    pub fn apply(&self) -> Result<()> {
+        let layout = self.clone();
+        with_com_objects(move |o| layout.apply_with(o))
+    }
+
+    /// Serialize the layout to JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::InvalidLayout(e.to_string()))
+    }
+
+    /// Deserialize the layout from JSON
+    pub fn from_json(json: &str) -> Result<Layout> {
+        serde_json::from_str(json).map_err(|e| Error::InvalidLayout(e.to_string()))
+    }
+
+    /// Serialize the layout to TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::InvalidLayout(e.to_string()))
+    }
+
+    /// Deserialize the layout from TOML
+    pub fn from_toml(toml: &str) -> Result<Layout> {
+        toml::from_str(toml).map_err(|e| Error::InvalidLayout(e.to_string()))
+    }
+
+    pub(crate) fn capture_with(o: &dyn VirtualDesktopBackend) -> Result<Layout> {
+        let mut layout = Layout::default();
+        let capabilities = o.get_capabilities()?;
+        for desktop in o.get_desktops()? {
+            layout.desktops.push(DesktopLayout {
+                id: o.get_desktop_id(&desktop)?,
+                name: match capabilities.desktop_names {
+                    true => o.get_desktop_name(&desktop)?,
+                    false => String::new(),
+                },
+                wallpaper: match capabilities.desktop_wallpapers {
+                    true => o.get_desktop_wallpaper(&desktop)?,
+                    false => String::new(),
+                },
+            });
+        }
+
+        for window in o.get_all_windows()? {
+            let Some((app_id, title)) = window_identity(o, &window.window)? else {
+                continue;
+            };
+            if window.is_pinned && o.is_app_id_pinned(&app_id)? {
+                if !layout.pinned_apps.contains(&app_id) {
+                    layout.pinned_apps.push(app_id);
+                }
+                continue;
+            }
+            let desktop = match window.desktop {
+                Some(desktop) => Some(o.get_desktop_index(&desktop)?),
+                None => None,
+            };
+            let window = WindowLayout {
+                app_id,
+                title,
+                desktop,
+            };
+            if desktop.is_some() {
+                layout.windows.push(window);
+            } else {
+                layout.pinned_windows.push(window);
+            }
+        }
+        Ok(layout)
+    }
+
+    pub(crate) fn apply_with(&self, o: &dyn VirtualDesktopBackend) -> Result<()> {
+        let desktops = self.apply_desktops(o)?;
+
+        for app_id in &self.pinned_apps {
+            if !o.is_app_id_pinned(app_id)? {
+                o.pin_app_id(app_id)?;
+            }
+        }
+
+        let mut windows = Vec::new();
+        for window in o.get_all_windows()? {
+            if let Some((app_id, title)) = window_identity(o, &window.window)? {
+                windows.push((window, app_id, title));
+            }
+        }
+
+        // Exact matches first, then by app ID only as titles change often,
+        // e.g. with the open document
+        let mut unused = self
+            .windows
+            .iter()
+            .chain(&self.pinned_windows)
+            .collect::<Vec<_>>();
+        let mut placements = Vec::new();
+        windows.retain(|(window, app_id, title)| {
+            let found = unused
+                .iter()
+                .position(|w| &w.app_id == app_id && &w.title == title);
+            if let Some(position) = found {
+                placements.push((*window, app_id.clone(), unused.remove(position)));
+            }
+            found.is_none()
+        });
+        for (window, app_id, _) in windows {
+            if let Some(position) = unused.iter().position(|w| w.app_id == app_id) {
+                placements.push((window, app_id, unused.remove(position)));
+            }
+        }
+
+        for (window, app_id, target) in placements {
+            match target.desktop {
+                None if !window.is_pinned => o.pin_window(&window.window)?,
+                None => {}
+                Some(index) => {
+                    let Some(id) = desktops.get(index as usize) else {
+                        continue;
+                    };
+                    let desktop: Desktop = (*id).into();
+                    if window.is_pinned {
+                        if o.is_app_id_pinned(&app_id)? {
+                            continue;
+                        }
+                        o.unpin_window(&window.window)?;
+                    }
+                    if window.desktop != Some(desktop) && o.can_move_window(&window.window)? {
+                        o.move_window_to_desktop(&window.window, &desktop)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Matches, creates, renames and reorders the desktops, returns the GUIDs
+    /// in the order of the layout
+    fn apply_desktops(&self, o: &dyn VirtualDesktopBackend) -> Result<Vec<GUID>> {
+        // Unsupported parts of the layout are skipped, e.g. the order and
+        // wallpapers on Windows 10
+        let capabilities = o.get_capabilities()?;
+        let name = |d: &Desktop| match capabilities.desktop_names {
+            true => o.get_desktop_name(d),
+            false => Ok(String::new()),
+        };
+        let mut existing = o
+            .get_desktops()?
+            .iter()
+            .map(|d| Ok((o.get_desktop_id(d)?, name(d)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut take = |f: &dyn Fn(&(GUID, String)) -> bool| {
+            let position = existing.iter().position(f)?;
+            Some(existing.remove(position).0)
+        };
+
+        // Matching by GUID goes first so that the names do not steal desktops
+        let mut matched = self
+            .desktops
+            .iter()
+            .map(|d| take(&|(id, _)| *id == d.id))
+            .collect::<Vec<_>>();
+        for (id, d) in matched.iter_mut().zip(&self.desktops) {
+            if id.is_none() && !d.name.is_empty() {
+                *id = take(&|(_, name)| *name == d.name);
+            }
+        }
+
+        let mut desktops = Vec::with_capacity(self.desktops.len());
+        for (index, (id, d)) in matched.into_iter().zip(&self.desktops).enumerate() {
+            let id = match id {
+                Some(id) => id,
+                None => o.get_desktop_id(&o.create_desktop()?)?,
+            };
+            let desktop = id.into();
+            if capabilities.desktop_names && name(&desktop)? != d.name {
+                o.set_desktop_name(&desktop, &d.name)?;
+            }
+            if capabilities.desktop_wallpapers
+                && !d.wallpaper.is_empty()
+                && o.get_desktop_wallpaper(&desktop)? != d.wallpaper
+            {
+                o.set_desktop_wallpaper(&desktop, &d.wallpaper)?;
+            }
+            if capabilities.move_desktop && o.get_desktop_index(&desktop)? != index as u32 {
+                o.move_desktop(&desktop, index as u32)?;
+            }
+            desktops.push(id);
+        }
+        Ok(desktops)
+    }
+}
+
+/// App ID and title of the window, `None` if the window was closed
+fn window_identity(
+    o: &dyn VirtualDesktopBackend,
+    window: &Window,
+) -> Result<Option<(String, String)>> {
+    let identity = o
+        .get_app_id(window)
+        .and_then(|app_id| Ok((app_id, o.get_window_title(window)?)));
+    match identity {
+        Ok(identity) => Ok(Some(identity)),
+        Err(Error::WindowNotFound(_)) => Ok(None),
+        Err(er) => Err(er),
+    }
+}
+
+/// GUIDs are written as "3F07F4BE-B107-441A-AF0F-39D82529072C"
+mod guid_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use windows::core::GUID;
+
+    pub fn serialize<S: Serializer>(guid: &GUID, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", guid))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<GUID, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let hex = value
+            .trim_matches(|c| c == '{' || c == '}')
+            .replace('-', "");
+        if hex.len() != 32 {
+            return Err(D::Error::custom(format!("invalid GUID {}", value)));
+        }
+        u128::from_str_radix(&hex, 16)
+            .map(GUID::from_u128)
+            .map_err(|_| D::Error::custom(format!("invalid GUID {}", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop_names(shell: &SimulatedShell) -> Vec<String> {
+        shell
+            .get_desktops()
+            .unwrap()
+            .iter()
+            .map(|d| shell.get_desktop_name(d).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_capture_and_apply() {
+        let shell = SimulatedShell::with_desktops(3);
+        for (index, name) in ["Mail", "Code", "Music"].iter().enumerate() {
+            shell
+                .set_desktop_name(&(index as u32).into(), name)
+                .unwrap();
+        }
+        let mail = shell.create_window("Microsoft.Outlook");
+        let editor = shell.create_window("Microsoft.VSCode");
+        shell.set_window_title(editor, "main.rs - crate").unwrap();
+        let player = shell.create_window("Spotify");
+        let notes = shell.create_window("Microsoft.StickyNotes");
+        shell.move_window_to_desktop(&editor, &1.into()).unwrap();
+        shell.move_window_to_desktop(&player, &2.into()).unwrap();
+        shell.pin_window(&notes).unwrap();
+
+        let layout = Layout::capture_with(&shell).unwrap();
+        assert_eq!(layout.desktops.len(), 3);
+        assert_eq!(layout.pinned_windows.len(), 1);
+        assert_eq!(layout.pinned_windows[0].app_id, "Microsoft.StickyNotes");
+        let editor_layout = layout
+            .windows
+            .iter()
+            .find(|w| w.app_id == "Microsoft.VSCode")
+            .unwrap();
+        assert_eq!(editor_layout.title, "main.rs - crate");
+        assert_eq!(editor_layout.desktop, Some(1));
+
+        // Mess up the desktops and windows
+        let music = shell.get_desktop_id(&2.into()).unwrap();
+        shell.remove_desktop(&music.into(), &0.into()).unwrap();
+        shell.move_desktop(&1.into(), 0).unwrap();
+        shell.set_desktop_name(&0.into(), "Renamed").unwrap();
+        shell.move_window_to_desktop(&mail, &0.into()).unwrap();
+        shell.unpin_window(&notes).unwrap();
+        shell.set_window_title(editor, "lib.rs - crate").unwrap();
+
+        layout.apply_with(&shell).unwrap();
+        assert_eq!(desktop_names(&shell), vec!["Mail", "Code", "Music"]);
+        let desktop_of = |hwnd| {
+            let desktop = shell.get_desktop_by_window(&hwnd).unwrap();
+            shell.get_desktop_index(&desktop).unwrap()
+        };
+        assert_eq!(desktop_of(mail), 0);
+        assert_eq!(desktop_of(editor), 1);
+        assert_eq!(desktop_of(player), 2);
+        assert!(shell.is_pinned_window(&notes).unwrap());
+    }
+
+    #[test]
+    fn test_layout_pinned_apps() {
+        let shell = SimulatedShell::with_desktops(1);
+        let terminal = shell.create_window("Microsoft.WindowsTerminal");
+        shell.pin_app(&terminal).unwrap();
+
+        let layout = Layout::capture_with(&shell).unwrap();
+        assert_eq!(layout.pinned_apps, vec!["Microsoft.WindowsTerminal"]);
+        assert!(layout.windows.is_empty());
+        assert!(layout.pinned_windows.is_empty());
+
+        shell.unpin_app(&terminal).unwrap();
+        layout.apply_with(&shell).unwrap();
+        assert!(shell.is_app_id_pinned("Microsoft.WindowsTerminal").unwrap());
+    }
+
+    #[test]
+    fn test_layout_windows_10() {
+        let shell = SimulatedShell::with_desktops(2);
+        shell.set_desktop_name(&1.into(), "Code").unwrap();
+        let editor = shell.create_window("Microsoft.VSCode");
+        shell.move_window_to_desktop(&editor, &1.into()).unwrap();
+        let mut layout = Layout::capture_with(&shell).unwrap();
+        layout.desktops[0].wallpaper = "C:\\wallpaper.jpg".to_owned();
+        layout.desktops.swap(0, 1);
+        layout.windows[0].desktop = Some(0);
+
+        // Wallpapers and the order are skipped
+        shell.set_capabilities(Capabilities::WINDOWS_10);
+        shell.set_desktop_name(&1.into(), "Renamed").unwrap();
+        shell.move_window_to_desktop(&editor, &0.into()).unwrap();
+        layout.apply_with(&shell).unwrap();
+        assert_eq!(desktop_names(&shell), vec!["", "Code"]);
+        let desktop = shell.get_desktop_by_window(&editor).unwrap();
+        assert_eq!(shell.get_desktop_index(&desktop).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_layout_serialization() {
+        let layout = Layout {
+            desktops: vec![DesktopLayout {
+                id: GUID::from_u128(0x3F07F4BE_B107_441A_AF0F_39D82529072C),
+                name: "Code".to_owned(),
+                wallpaper: "C:\\wallpaper.jpg".to_owned(),
+            }],
+            pinned_apps: vec!["Microsoft.WindowsTerminal".to_owned()],
+            pinned_windows: vec![WindowLayout {
+                app_id: "Microsoft.StickyNotes".to_owned(),
+                title: String::new(),
+                desktop: None,
+            }],
+            windows: vec![WindowLayout {
+                app_id: "Microsoft.VSCode".to_owned(),
+                title: "main.rs - crate".to_owned(),
+                desktop: Some(0),
+            }],
+        };
+
+        let json = layout.to_json().unwrap();
+        assert!(json.contains("\"3F07F4BE-B107-441A-AF0F-39D82529072C\""));
+        assert_eq!(Layout::from_json(&json).unwrap(), layout);
+
+        let toml = layout.to_toml().unwrap();
+        assert_eq!(Layout::from_toml(&toml).unwrap(), layout);
+
+        let minimal = Layout::from_toml(
+            "[[desktops]]\nid = \"{3F07F4BE-B107-441A-AF0F-39D82529072C}\"\nname = \"Code\"\n",
+        )
+        .unwrap();
+        assert_eq!(minimal.desktops[0].id, layout.desktops[0].id);
+        assert!(minimal.windows.is_empty());
+
+        assert!(matches!(
+            Layout::from_json("{\"desktops\": [{\"id\": \"nope\"}]}"),
+            Err(Error::InvalidLayout(_))
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 //! winvd - crate for accessing the Windows Virtual Desktop API
 //!
 //! All functions taking `Into<Desktop>` can take either a index or a GUID.
+//! Functions taking `Into<Window>` can take either a `Window` or a `HWND`.
 //!
 //! # Examples
 //! * Get first desktop name by index `get_desktop(0).get_name()`
@@ -8,22 +9,55 @@
 //! * Get desktop name by GUID `get_desktop(GUID(123...)).get_name()`
 //! * Switch to fifth desktop by index `switch_desktop(4)`
 //! * Get third desktop name `get_desktop(2).get_name()`
+//!
+//! The functions use the Windows shell by default, `set_backend` can select
+//! the in-memory `SimulatedShell` instead.
+#[cfg(feature = "async")]
+pub mod r#async;
+mod backend;
+#[cfg(windows)]
 mod comobjects;
 mod desktop;
+mod error;
 mod events;
+mod executor;
+mod hub;
+#[cfg(windows)]
 mod interfaces;
+#[cfg(feature = "layout")]
+mod layout;
 mod listener;
 mod log;
+mod retry;
+mod simulator;
+mod version;
+mod window;
 
 #[cfg(feature = "integration-tests")]
 #[cfg(test)]
 mod tests;
 
-pub use comobjects::Error;
+pub use backend::{get_backend, set_backend, Backend, VirtualDesktopBackend};
 pub use desktop::*;
+pub use error::{ComError, Error, ErrorTarget};
 pub use events::*;
-pub use listener::DesktopEventThread;
+pub use executor::ComThread;
+pub use hub::{
+    set_event_hub_listener, subscribe_desktop_events, subscribe_desktop_events_filtered,
+    Subscription,
+};
+#[cfg(feature = "layout")]
+pub use layout::*;
+pub use listener::{Apartment, DesktopEventThread, ListenerBuilder, ThreadPriority};
+pub use retry::{get_retry_policy, set_retry_policy, with_retry_policy, Retried, RetryPolicy};
+pub use simulator::SimulatedShell;
+pub use version::get_windows_build;
+pub use window::*;
 pub type Result<T> = std::result::Result<T, Error>;
 
+pub(crate) use backend::with_com_objects;
+pub(crate) use desktop::DesktopInternal;
+
+#[cfg(windows)]
 #[macro_use]
 extern crate macro_rules_attribute;
@@ -1,20 +1,51 @@
-use std::convert::TryInto;
-use std::pin::Pin;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use crate::comobjects::ComObjects;
-use crate::interfaces::{
-    ComIn, IApplicationView, IVirtualDesktop, IVirtualDesktopNotification,
-    IVirtualDesktopNotification_Impl,
-};
 use crate::log::log_output;
 use crate::DesktopEventSender;
-use crate::{DesktopEvent, Result};
+use crate::{
+    desktop::DesktopInternal, get_backend, Desktop, DesktopEvent, DesktopWindow, Error, EventMask,
+    Result, VirtualDesktopBackend, Window,
+};
+use windows::core::GUID;
 
-use windows::core::{Interface, HRESULT, HSTRING};
+#[cfg(windows)]
+use crate::comobjects::HRESULTHelpers;
+#[cfg(windows)]
+use crate::interfaces::{
+    windows10, ComIn, IApplicationView, IVirtualDesktop, IVirtualDesktopNotification,
+    IVirtualDesktopNotificationMonitors, IVirtualDesktopNotificationMonitors_Impl,
+    IVirtualDesktopNotification_Impl,
+};
+#[cfg(windows)]
+use std::collections::HashMap;
+#[cfg(windows)]
+use std::convert::TryInto;
+#[cfg(windows)]
+use std::time::Instant;
+#[cfg(windows)]
+use windows::core::{HRESULT, HSTRING};
+#[cfg(windows)]
 use windows::Win32::Foundation::HWND;
+#[cfg(windows)]
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+#[cfg(windows)]
+use windows::Win32::System::Com::{
+    CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED, COINIT_MULTITHREADED,
+};
+#[cfg(windows)]
 use windows::Win32::System::Threading::{
-    GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
+    GetCurrentThread, GetCurrentThreadId, SetThreadPriority, THREAD_PRIORITY,
+    THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_HIGHEST,
+    THREAD_PRIORITY_LOWEST, THREAD_PRIORITY_NORMAL, THREAD_PRIORITY_TIME_CRITICAL,
+};
+#[cfg(windows)]
+use windows::Win32::UI::Shell::Common::IObjectArray;
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{
+    DispatchMessageW, GetWindowThreadProcessId, IsWindow, MsgWaitForMultipleObjects, PeekMessageW,
+    PostThreadMessageW, TranslateMessage, MSG, PM_NOREMOVE, PM_REMOVE, QS_ALLINPUT, WM_QUIT,
 };
 
 // Log format macro
@@ -29,6 +60,183 @@ enum DekstopEventThreadMsg {
     Quit,
 }
 
+/// Predicate of the events sent by the listener
+pub(crate) type EventFilter = Arc<dyn Fn(&DesktopEvent) -> bool + Send + Sync>;
+
+/// Priority of the listener thread
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ThreadPriority {
+    Lowest,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+
+    /// Explorer waits for the listener while it handles the notification, so
+    /// this is the default
+    #[default]
+    Highest,
+
+    /// Above all normal threads, the listener is never delayed but a slow
+    /// sender also starves the other threads of the process
+    TimeCritical,
+}
+
+#[cfg(windows)]
+impl From<ThreadPriority> for THREAD_PRIORITY {
+    fn from(priority: ThreadPriority) -> Self {
+        match priority {
+            ThreadPriority::Lowest => THREAD_PRIORITY_LOWEST,
+            ThreadPriority::BelowNormal => THREAD_PRIORITY_BELOW_NORMAL,
+            ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+            ThreadPriority::AboveNormal => THREAD_PRIORITY_ABOVE_NORMAL,
+            ThreadPriority::Highest => THREAD_PRIORITY_HIGHEST,
+            ThreadPriority::TimeCritical => THREAD_PRIORITY_TIME_CRITICAL,
+        }
+    }
+}
+
+/// COM apartment of the listener thread
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Apartment {
+    /// Single-threaded apartment, the notifications are delivered as window
+    /// messages so the thread always pumps messages
+    Sta,
+
+    /// Multithreaded apartment, the notifications are called from the RPC
+    /// threads, possibly from several at once
+    #[default]
+    Mta,
+}
+
+/// Configures and starts an event listener thread
+///
+/// `listen_desktop_events(sender)` is the same as
+/// `ListenerBuilder::new().spawn(sender)`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// use std::time::Duration;
+///
+/// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+/// let _listener = ListenerBuilder::new()
+///     .interval(Duration::from_secs(1))
+///     .priority(ThreadPriority::AboveNormal)
+///     .name("desktop-events")
+///     .filter(|event| !matches!(event, DesktopEvent::WindowChanged(_)))
+///     .spawn(tx)
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ListenerBuilder {
+    interval: Duration,
+    priority: ThreadPriority,
+    name: Option<String>,
+    apartment: Apartment,
+    message_pump: bool,
+    mask: EventMask,
+    filter: Option<EventFilter>,
+}
+
+impl Default for ListenerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ListenerBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ListenerBuilder")
+            .field("interval", &self.interval)
+            .field("priority", &self.priority)
+            .field("name", &self.name)
+            .field("apartment", &self.apartment)
+            .field("message_pump", &self.message_pump)
+            .field("mask", &self.mask)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+impl ListenerBuilder {
+    pub fn new() -> Self {
+        ListenerBuilder {
+            interval: Duration::from_secs(3),
+            priority: ThreadPriority::default(),
+            name: None,
+            apartment: Apartment::default(),
+            message_pump: false,
+            mask: EventMask::ALL,
+            filter: None,
+        }
+    }
+
+    /// How often the connection to the shell is checked, and the listener
+    /// registered again if needed, 3 seconds by default
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Priority of the thread, `ThreadPriority::Highest` by default
+    ///
+    /// Explorer waits for the listener while it handles a notification, a
+    /// higher priority keeps the shell responsive when the process is busy.
+    /// `TimeCritical` preempts even the other time critical threads, use it
+    /// only if the sender returns quickly.
+    pub fn priority(mut self, priority: ThreadPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Name of the thread, shown e.g. in debuggers
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// COM apartment of the thread, `Apartment::Mta` by default
+    pub fn apartment(mut self, apartment: Apartment) -> Self {
+        self.apartment = apartment;
+        self
+    }
+
+    /// Dispatch window messages on the thread between the connection checks,
+    /// needed e.g. by windows created from the event callbacks. Always on with
+    /// `Apartment::Sta`.
+    pub fn message_pump(mut self, enabled: bool) -> Self {
+        self.message_pump = enabled;
+        self
+    }
+
+    /// Kinds of the events to send, all by default. Events not in the mask
+    /// are not created at all.
+    pub fn mask(mut self, mask: EventMask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Send only the events for which the predicate returns true, it's called
+    /// on the listener thread before the event is converted and sent
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&DesktopEvent) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Start the listener thread with the sender, see `listen_desktop_events`
+    pub fn spawn<T, S>(self, sender: S) -> Result<DesktopEventThread>
+    where
+        T: From<DesktopEvent> + Clone + Send + 'static,
+        S: Into<DesktopEventSender<T>>,
+    {
+        DesktopEventThread::start(self, sender.into())
+    }
+}
+
 /// Event listener thread, create with `listen_desktop_events(sender)`,
 /// value must be held in the state of the program, the thread is joined when
 /// the value is dropped.
@@ -36,6 +244,10 @@ enum DekstopEventThreadMsg {
 pub struct DesktopEventThread {
     thread_control_sender: Option<std::sync::mpsc::Sender<DekstopEventThreadMsg>>,
     thread: Option<std::thread::JoinHandle<()>>,
+
+    /// Woken with `WM_QUIT` when the thread pumps messages
+    #[cfg(windows)]
+    native_thread_id: Option<u32>,
 }
 
 impl DesktopEventThread {
@@ -43,62 +255,173 @@ impl DesktopEventThread {
     where
         T: From<DesktopEvent> + Clone + Send + 'static,
     {
+        Self::start(ListenerBuilder::new(), sender)
+    }
+
+    fn start<T>(options: ListenerBuilder, sender: DesktopEventSender<T>) -> Result<Self>
+    where
+        T: From<DesktopEvent> + Clone + Send + 'static,
+    {
+        let backend = get_backend();
+        if !backend.is_available() {
+            return Err(Error::BackendNotAvailable);
+        }
+
         // Channel for quitting
         let (tx, rx) = std::sync::mpsc::channel::<DekstopEventThreadMsg>();
 
-        // Main notification thread, with STA message loop
-        let notification_thread = std::thread::spawn(move || {
-            let com_objects = ComObjects::new();
-            log_format!("Listener thread started {:?}", std::thread::current().id());
-
-            // Set thread priority to time critical, explorer.exe really hates if your listener thread is slow
-            let _ = unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) };
-
-            // Create listener
-            let sender_new = sender.clone();
-            let mut listener = VirtualDesktopNotificationWrapper::new(
-                &com_objects,
-                Box::new(move |event| {
-                    sender_new.try_send(event.into());
-                }),
-            );
-
-            loop {
-                let item = rx.recv_timeout(Duration::from_secs(3));
-                match item {
-                    Ok(DekstopEventThreadMsg::Quit) => {
-                        log_output("Listener thread received quit message");
-                        break;
-                    }
-                    Err(_) => {
-                        if !com_objects.is_connected() || listener.is_err() {
-                            log_output(
-                                "Listener is not connected, or failed to register, trying again",
-                            );
-
-                            // Drop will unregister the old listener before the
-                            // new one is created, this is required, read more
-                            // from note-IVirtualDesktopNotification.md
-                            drop(listener);
-                            let sender_new = sender.clone();
-                            listener = VirtualDesktopNotificationWrapper::new(
-                                &com_objects,
-                                Box::new(move |event| {
+        // Channel for waiting until the first registration is done, so that
+        // events caused right after creating the listener are not lost, gives
+        // the native thread ID if the thread pumps messages
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Option<u32>>();
+
+        let message_pump = options.message_pump || options.apartment == Apartment::Sta;
+        let mut thread = std::thread::Builder::new();
+        if let Some(name) = &options.name {
+            thread = thread.name(name.clone());
+        }
+
+        // Main notification thread
+        let notification_thread = thread
+            .spawn(move || {
+                #[cfg(windows)]
+                let com_initialized = unsafe {
+                    CoInitializeEx(
+                        None,
+                        match options.apartment {
+                            Apartment::Sta => COINIT_APARTMENTTHREADED,
+                            Apartment::Mta => COINIT_MULTITHREADED,
+                        },
+                    )
+                }
+                .is_ok();
+
+                let _ = backend.with_dedicated(|backend| {
+                    log_format!("Listener thread started {:?}", std::thread::current().id());
+
+                    // Explorer.exe really hates if your listener thread is slow
+                    #[cfg(windows)]
+                    let _ =
+                        unsafe { SetThreadPriority(GetCurrentThread(), options.priority.into()) };
+
+                    let (mask, filter) = (options.mask, options.filter.clone());
+                    let accepts = move |event: &DesktopEvent| {
+                        mask.matches(event) && filter.as_ref().is_none_or(|f| f(event))
+                    };
+                    let send = |event: DesktopEvent| {
+                        if accepts(&event) {
+                            sender.try_send(event.into());
+                        }
+                    };
+
+                    // Desktops are taken once after registering and kept up
+                    // to date from the events, diffed after reconnecting. Not
+                    // needed if the diffed events are not sent.
+                    let track = mask.intersects(DesktopSnapshot::KINDS);
+                    let snapshot = Arc::new(Mutex::new(None::<DesktopSnapshot>));
+                    let take_snapshot =
+                        || track.then(|| DesktopSnapshot::take(backend).ok()).flatten();
+
+                    let register = || {
+                        let sender_new = sender.clone();
+                        let accepts = accepts.clone();
+                        let snapshot = snapshot.clone();
+                        VirtualDesktopNotificationWrapper::new(
+                            backend,
+                            Box::new(move |event| {
+                                if let Some(snapshot) = lock(&snapshot).as_mut() {
+                                    snapshot.apply(&event);
+                                }
+                                if accepts(&event) {
                                     sender_new.try_send(event.into());
-                                }),
-                            );
+                                }
+                            }),
+                            if track {
+                                mask | DesktopSnapshot::KINDS
+                            } else {
+                                mask
+                            },
+                        )
+                    };
+
+                    // Create listener
+                    let mut listener = register();
+                    let _ = ready_tx.send(message_pump.then(native_thread_id).flatten());
+                    match &listener {
+                        Ok(_) => *lock(&snapshot) = take_snapshot(),
+                        Err(er) => {
+                            send(DesktopEvent::ListenerRegistrationFailed { error: er.clone() })
                         }
                     }
-                }
-            }
+                    let mut connected = listener.is_ok();
 
-            log_format!("Listener thread finished {:?}", std::thread::current().id());
-        });
+                    // Reconnecting is reported only after a disconnect, not
+                    // when the first registration succeeds on a retry
+                    let mut was_connected = connected;
+
+                    loop {
+                        if wait_for_quit(&rx, options.interval, message_pump) {
+                            log_output("Listener thread received quit message");
+                            break;
+                        }
+                        if backend.is_connected() && listener.is_ok() {
+                            continue;
+                        }
+
+                        log_output(
+                            "Listener is not connected, or failed to register, trying again",
+                        );
+                        if connected {
+                            connected = false;
+                            send(DesktopEvent::ShellDisconnected);
+                        }
+
+                        // Drop will unregister the old listener before the
+                        // new one is created, this is required, read more
+                        // from note-IVirtualDesktopNotification.md
+                        drop(listener);
+                        listener = register();
+                        match &listener {
+                            Ok(_) if !was_connected => {
+                                connected = true;
+                                was_connected = true;
+                                *lock(&snapshot) = take_snapshot();
+                            }
+                            Ok(_) => {
+                                connected = true;
+                                send(DesktopEvent::ShellReconnected);
+                                let current = take_snapshot();
+                                let old = lock(&snapshot).take();
+                                if let (Some(old), Some(new)) = (&old, &current) {
+                                    old.diff(new).into_iter().for_each(send);
+                                }
+                                *lock(&snapshot) = current.or(old);
+                            }
+                            Err(er) => {
+                                send(DesktopEvent::ListenerRegistrationFailed { error: er.clone() })
+                            }
+                        }
+                    }
+
+                    log_format!("Listener thread finished {:?}", std::thread::current().id());
+                    Ok(())
+                });
+
+                // COM objects of the backend are released by `with_dedicated`
+                #[cfg(windows)]
+                if com_initialized {
+                    unsafe { CoUninitialize() };
+                }
+            })
+            .map_err(|e| Error::ThreadSpawnFailed(e.to_string()))?;
+        let _native_thread_id = ready_rx.recv().ok().flatten();
 
         // Store the new thread
         Ok(DesktopEventThread {
             thread_control_sender: Some(tx),
             thread: Some(notification_thread),
+            #[cfg(windows)]
+            native_thread_id: _native_thread_id,
         })
     }
 
@@ -107,6 +430,11 @@ impl DesktopEventThread {
     pub fn stop(&mut self) -> std::thread::Result<()> {
         if let Some(thread_control_sender) = self.thread_control_sender.take() {
             let _ = thread_control_sender.send(DekstopEventThreadMsg::Quit);
+
+            #[cfg(windows)]
+            if let Some(id) = self.native_thread_id {
+                let _ = unsafe { PostThreadMessageW(id, WM_QUIT, WPARAM(0), LPARAM(0)) };
+            }
         }
 
         if let Some(thread) = self.thread.take() {
@@ -127,40 +455,87 @@ impl Drop for DesktopEventThread {
     }
 }
 
-/// Wrapper registers the actual IVirtualDesktopNotification and on drop unregisters the notification
+/// ID of the current thread for posting messages to it, the message queue is
+/// created first
+#[cfg(windows)]
+fn native_thread_id() -> Option<u32> {
+    unsafe {
+        let mut msg = MSG::default();
+        let _ = PeekMessageW(&mut msg, HWND::default(), 0, 0, PM_NOREMOVE);
+        Some(GetCurrentThreadId())
+    }
+}
+
+#[cfg(not(windows))]
+fn native_thread_id() -> Option<u32> {
+    None
+}
+
+/// Waits for the quit message at most the interval, dispatching window
+/// messages meanwhile if pumping. Returns true if the thread should quit.
+fn wait_for_quit(rx: &Receiver<DekstopEventThreadMsg>, interval: Duration, pump: bool) -> bool {
+    #[cfg(windows)]
+    if pump {
+        let deadline = Instant::now() + interval;
+        loop {
+            match rx.try_recv() {
+                Ok(DekstopEventThreadMsg::Quit) => return true,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => return true,
+                Err(std::sync::mpsc::TryRecvError::Empty) => {}
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            unsafe {
+                let millis = remaining.as_millis().min(u32::MAX as u128) as u32;
+                MsgWaitForMultipleObjects(None, false, millis, QS_ALLINPUT);
+                let mut msg = MSG::default();
+                while PeekMessageW(&mut msg, HWND::default(), 0, 0, PM_REMOVE).as_bool() {
+                    // Posted by `stop`, the quit message is in the channel
+                    if msg.message == WM_QUIT {
+                        break;
+                    }
+                    TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(windows))]
+    let _ = pump;
+
+    match rx.recv_timeout(interval) {
+        Ok(DekstopEventThreadMsg::Quit) | Err(RecvTimeoutError::Disconnected) => true,
+        Err(RecvTimeoutError::Timeout) => false,
+    }
+}
+
+/// Wrapper registers the notification callback and on drop unregisters it
 struct VirtualDesktopNotificationWrapper<'a> {
-    #[allow(dead_code)]
-    ptr: Pin<Box<IVirtualDesktopNotification>>,
     cookie: u32,
-    com_objects: &'a ComObjects,
+    backend: &'a dyn VirtualDesktopBackend,
 }
 
 impl<'a> VirtualDesktopNotificationWrapper<'a> {
     pub fn new(
-        com_objects: &'a ComObjects,
-        sender: Box<dyn Fn(DesktopEvent)>,
-    ) -> Result<Pin<Box<VirtualDesktopNotificationWrapper>>> {
-        let ptr: Pin<Box<IVirtualDesktopNotification>> =
-            Pin::new(Box::new(VirtualDesktopNotification { sender }.into()));
-        let raw_ptr = ptr.as_raw();
-        let cookie = com_objects.register_for_notifications(raw_ptr)?;
-        let notification = Pin::new(Box::new(VirtualDesktopNotificationWrapper {
-            com_objects,
-            cookie,
-            ptr,
-        }));
+        backend: &'a dyn VirtualDesktopBackend,
+        sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+        mask: EventMask,
+    ) -> Result<VirtualDesktopNotificationWrapper<'a>> {
+        let cookie = backend.register_for_notifications(sender, mask)?;
         log_format!(
-            "Registered notification {:?} {} {:?}",
-            raw_ptr,
-            notification.cookie,
+            "Registered notification {} {:?}",
+            cookie,
             std::thread::current().id()
         );
 
-        Ok(notification)
+        Ok(VirtualDesktopNotificationWrapper { cookie, backend })
     }
 }
 
-impl<'a> Drop for VirtualDesktopNotificationWrapper<'a> {
+impl Drop for VirtualDesktopNotificationWrapper<'_> {
     fn drop(&mut self) {
         log_format!(
             "Unregistering notification {} {:?}",
@@ -169,15 +544,313 @@ impl<'a> Drop for VirtualDesktopNotificationWrapper<'a> {
         );
 
         let cookie = self.cookie;
-        let _ = self.com_objects.unregister_for_notifications(cookie);
+        let _ = self.backend.unregister_for_notifications(cookie);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Desktops and their names, compared after reconnecting to the shell
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DesktopSnapshot {
+    desktops: Vec<(GUID, String)>,
+    current: GUID,
+}
+
+impl DesktopSnapshot {
+    /// Kinds of the events the snapshot is updated from, and `diff` creates
+    pub(crate) const KINDS: EventMask = EventMask::DESKTOP_CREATED
+        .union(EventMask::DESKTOP_DESTROYED)
+        .union(EventMask::DESKTOP_CHANGED)
+        .union(EventMask::RENAMED)
+        .union(EventMask::DESKTOP_MOVED);
+
+    pub(crate) fn take(backend: &dyn VirtualDesktopBackend) -> Result<Self> {
+        let desktops = backend
+            .get_desktops()?
+            .iter()
+            .map(|desktop| {
+                let name = match backend.get_desktop_name(desktop) {
+                    Err(Error::NotSupported) => String::new(),
+                    name => name?,
+                };
+                Ok((backend.get_desktop_id(desktop)?, name))
+            })
+            .collect::<Result<_>>()?;
+        let current = backend.get_desktop_id(&backend.get_current_desktop()?)?;
+        Ok(DesktopSnapshot { desktops, current })
+    }
+
+    /// Update the snapshot from the event, events of desktops given by index
+    /// are ignored
+    pub(crate) fn apply(&mut self, event: &DesktopEvent) {
+        let id = |desktop: &Desktop| match DesktopInternal::from(*desktop) {
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => Some(id),
+            DesktopInternal::Index(_) => None,
+        };
+        let position = |desktops: &[(GUID, String)], id: GUID| {
+            desktops.iter().position(|(other, _)| *other == id)
+        };
+        match event {
+            DesktopEvent::DesktopCreated(desktop) => {
+                if let Some(id) = id(desktop) {
+                    if position(&self.desktops, id).is_none() {
+                        self.desktops.push((id, String::new()));
+                    }
+                }
+            }
+            DesktopEvent::DesktopDestroyed { destroyed, .. } => {
+                if let Some(id) = id(destroyed) {
+                    self.desktops.retain(|(other, _)| *other != id);
+                }
+            }
+            DesktopEvent::DesktopChanged { new, .. } => {
+                if let Some(id) = id(new) {
+                    self.current = id;
+                }
+            }
+            DesktopEvent::DesktopNameChanged(desktop, name) => {
+                if let Some(index) = id(desktop).and_then(|id| position(&self.desktops, id)) {
+                    self.desktops[index].1 = name.clone();
+                }
+            }
+            DesktopEvent::DesktopMoved {
+                desktop, new_index, ..
+            } => {
+                if let Some(index) = id(desktop).and_then(|id| position(&self.desktops, id)) {
+                    let moved = self.desktops.remove(index);
+                    let new_index = (*new_index).clamp(0, self.desktops.len() as i64) as usize;
+                    self.desktops.insert(new_index, moved);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Events that turn this snapshot into the newer one, removed desktops are
+    /// reported with the new current desktop as the fallback
+    pub(crate) fn diff(&self, new: &DesktopSnapshot) -> Vec<DesktopEvent> {
+        let name_in = |snapshot: &DesktopSnapshot, id: &GUID| {
+            snapshot
+                .desktops
+                .iter()
+                .find(|(other, _)| other == id)
+                .map(|(_, name)| name.clone())
+        };
+        let mut events = Vec::new();
+        for (id, _) in &self.desktops {
+            if name_in(new, id).is_none() {
+                events.push(DesktopEvent::DesktopDestroyed {
+                    destroyed: (*id).into(),
+                    fallback: new.current.into(),
+                });
+            }
+        }
+        for (id, name) in &new.desktops {
+            match name_in(self, id) {
+                None => {
+                    events.push(DesktopEvent::DesktopCreated((*id).into()));
+                    if !name.is_empty() {
+                        events.push(DesktopEvent::DesktopNameChanged((*id).into(), name.clone()));
+                    }
+                }
+                Some(old_name) if &old_name != name => {
+                    events.push(DesktopEvent::DesktopNameChanged((*id).into(), name.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        if self.current != new.current {
+            events.push(DesktopEvent::DesktopChanged {
+                new: new.current.into(),
+                old: self.current.into(),
+            });
+        }
+        events
+    }
+}
+
+// Desktop IDs the shell gives to views of pinned windows and pinned apps
+#[cfg(windows)]
+const WINDOW_ON_ALL_DESKTOPS: GUID = GUID::from_u128(0xC2DDEA68_66F2_4CF9_8264_1BFD00FBBBAC);
+#[cfg(windows)]
+const APP_ON_ALL_DESKTOPS: GUID = GUID::from_u128(0xBB64D5B7_4DE3_4AB2_A87C_DB7601AEA7DC);
+
+/// Where the view of a window is shown, tracked to tell what changed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum ViewPlacement {
+    Desktop(Desktop),
+    Pinned,
+}
+
+impl From<&DesktopWindow> for ViewPlacement {
+    fn from(window: &DesktopWindow) -> Self {
+        match window.desktop {
+            Some(desktop) if !window.is_pinned => ViewPlacement::Desktop(desktop),
+            _ => ViewPlacement::Pinned,
+        }
+    }
+}
+
+impl ViewPlacement {
+    /// Placement from the view's desktop ID, `None` if the view is not shown
+    #[cfg(windows)]
+    fn from_desktop_id(id: GUID) -> Option<Self> {
+        match id {
+            id if id == GUID::default() => None,
+            WINDOW_ON_ALL_DESKTOPS | APP_ON_ALL_DESKTOPS => Some(ViewPlacement::Pinned),
+            id => Some(ViewPlacement::Desktop(id.into())),
+        }
     }
 }
 
-#[windows::core::implement(IVirtualDesktopNotification)]
-struct VirtualDesktopNotification {
-    sender: Box<dyn Fn(DesktopEvent)>,
+/// Event for the window whose view moved from `old` placement to `new`, `old`
+/// is `None` if the window was not seen before
+pub(crate) fn window_event(
+    window: Window,
+    old: Option<ViewPlacement>,
+    new: ViewPlacement,
+) -> Option<DesktopEvent> {
+    match (old, new) {
+        (None, ViewPlacement::Desktop(desktop)) => Some(DesktopEvent::WindowAppeared {
+            window,
+            desktop: Some(desktop),
+        }),
+        (None, ViewPlacement::Pinned) => Some(DesktopEvent::WindowAppeared {
+            window,
+            desktop: None,
+        }),
+        (Some(ViewPlacement::Desktop(from)), ViewPlacement::Desktop(to)) if from != to => {
+            Some(DesktopEvent::WindowMovedToDesktop { window, from, to })
+        }
+        (Some(ViewPlacement::Desktop(from)), ViewPlacement::Pinned) => {
+            Some(DesktopEvent::WindowPinned { window, from })
+        }
+        (Some(ViewPlacement::Pinned), ViewPlacement::Desktop(to)) => {
+            Some(DesktopEvent::WindowUnpinned { window, to })
+        }
+        _ => None,
+    }
+}
+
+/// Placement of a window's view, with the thread owning the window to tell a
+/// reused window handle from the destroyed window
+#[cfg(windows)]
+#[derive(Copy, Clone, Debug)]
+struct ViewEntry {
+    placement: ViewPlacement,
+    thread_id: u32,
+}
+
+/// Thread owning the window, 0 if the window does not exist
+#[cfg(windows)]
+fn window_thread_id(hwnd: HWND) -> u32 {
+    unsafe { GetWindowThreadProcessId(hwnd, None) }
+}
+
+/// Notification of all the compiled layouts, registered as the one of the
+/// interface table of the running build
+#[cfg(windows)]
+#[windows::core::implement(
+    IVirtualDesktopNotification,
+    IVirtualDesktopNotificationMonitors,
+    windows10::IVirtualDesktopNotification
+)]
+pub(crate) struct VirtualDesktopNotification {
+    sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+    mask: EventMask,
+
+    /// Placement of each window's view, by window handle. Locked because in
+    /// the MTA the notifications are called from several RPC threads at once.
+    views: Mutex<HashMap<isize, ViewEntry>>,
+}
+
+#[cfg(windows)]
+impl VirtualDesktopNotification {
+    /// Windows are the currently known windows, so that their first change is
+    /// not reported as `WindowAppeared`
+    pub(crate) fn new(
+        sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+        mask: EventMask,
+        windows: Vec<DesktopWindow>,
+    ) -> Self {
+        let views = windows
+            .iter()
+            .map(|window| {
+                let hwnd = window.window.hwnd();
+                let entry = ViewEntry {
+                    placement: ViewPlacement::from(window),
+                    thread_id: window_thread_id(hwnd),
+                };
+                (hwnd.0, entry)
+            })
+            .collect();
+        VirtualDesktopNotification {
+            sender,
+            mask,
+            views: Mutex::new(views),
+        }
+    }
+
+    /// Creates and sends the event if its kind is in the mask
+    fn send(&self, kind: EventMask, event: impl FnOnce() -> Result<DesktopEvent>) -> Result<()> {
+        if self.mask.contains(kind) {
+            (self.sender)(event()?);
+        }
+        Ok(())
+    }
+
+    /// Sends `WindowChanged` and the event of the view's new placement
+    fn view_changed(&self, view: &IApplicationView) -> Result<()> {
+        if !self.mask.intersects(EventMask::WINDOWS) {
+            return Ok(());
+        }
+        let mut hwnd = HWND::default();
+        unsafe { view.get_thumbnail_window(&mut hwnd).as_result()? };
+        self.send(EventMask::WINDOW_CHANGED, || {
+            Ok(DesktopEvent::WindowChanged(hwnd.into()))
+        })?;
+
+        let mut desktop_id = GUID::default();
+        unsafe { view.get_virtual_desktop_id(&mut desktop_id).as_result()? };
+        let mut views = lock(&self.views);
+
+        // View without a desktop is hidden, it appears again later, and a
+        // destroyed window is forgotten
+        let thread_id = window_thread_id(hwnd);
+        let new = match ViewPlacement::from_desktop_id(desktop_id) {
+            Some(new) if thread_id != 0 => new,
+            _ => {
+                views.remove(&hwnd.0);
+                return Ok(());
+            }
+        };
+        let entry = ViewEntry {
+            placement: new,
+            thread_id,
+        };
+        let old = views
+            .insert(hwnd.0, entry)
+            .filter(|old| old.thread_id == thread_id)
+            .map(|old| old.placement);
+
+        // Entries of the destroyed windows are dropped when a new window is
+        // seen, so the map has only the existing windows
+        if old.is_none() {
+            views.retain(|hwnd, _| unsafe { IsWindow(HWND(*hwnd)) }.as_bool());
+        }
+        drop(views);
+        match window_event(hwnd.into(), old, new) {
+            Some(event) if self.mask.matches(&event) => (self.sender)(event),
+            _ => {}
+        }
+        Ok(())
+    }
 }
 
+#[cfg(windows)]
 fn eat_error<T>(func: impl FnOnce() -> Result<T>) -> Option<T> {
     let res = func();
     match res {
@@ -190,6 +863,7 @@ fn eat_error<T>(func: impl FnOnce() -> Result<T>) -> Option<T> {
 }
 
 // Allow unused variable warnings
+#[cfg(windows)]
 #[allow(unused_variables)]
 impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
     unsafe fn current_virtual_desktop_changed(
@@ -198,10 +872,12 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
         desktop_new: ComIn<IVirtualDesktop>,
     ) -> HRESULT {
         eat_error(|| {
-            Ok((self.sender)(DesktopEvent::DesktopChanged {
-                old: desktop_old.try_into()?,
-                new: desktop_new.try_into()?,
-            }))
+            self.send(EventMask::DESKTOP_CHANGED, || {
+                Ok(DesktopEvent::DesktopChanged {
+                    old: desktop_old.try_into()?,
+                    new: desktop_new.try_into()?,
+                })
+            })
         });
         HRESULT(0)
     }
@@ -212,19 +888,21 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
         name: HSTRING,
     ) -> HRESULT {
         eat_error(|| {
-            Ok((self.sender)(DesktopEvent::DesktopWallpaperChanged(
-                desktop.try_into()?,
-                name.to_string(),
-            )))
+            self.send(EventMask::WALLPAPER_CHANGED, || {
+                Ok(DesktopEvent::DesktopWallpaperChanged(
+                    desktop.try_into()?,
+                    name.to_string(),
+                ))
+            })
         });
         HRESULT(0)
     }
 
     unsafe fn virtual_desktop_created(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT {
         eat_error(|| {
-            Ok((self.sender)(DesktopEvent::DesktopCreated(
-                desktop.try_into()?,
-            )))
+            self.send(EventMask::DESKTOP_CREATED, || {
+                Ok(DesktopEvent::DesktopCreated(desktop.try_into()?))
+            })
         });
         HRESULT(0)
     }
@@ -234,6 +912,14 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
         desktop_destroyed: ComIn<IVirtualDesktop>,
         desktop_fallback: ComIn<IVirtualDesktop>,
     ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_DESTROYED, || {
+                Ok(DesktopEvent::DesktopDestroyBegin {
+                    destroyed: desktop_destroyed.try_into()?,
+                    fallback: desktop_fallback.try_into()?,
+                })
+            })
+        });
         HRESULT(0)
     }
 
@@ -242,6 +928,14 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
         desktop_destroyed: ComIn<IVirtualDesktop>,
         desktop_fallback: ComIn<IVirtualDesktop>,
     ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_DESTROYED, || {
+                Ok(DesktopEvent::DesktopDestroyFailed {
+                    destroyed: desktop_destroyed.try_into()?,
+                    fallback: desktop_fallback.try_into()?,
+                })
+            })
+        });
         HRESULT(0)
     }
 
@@ -252,10 +946,12 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
     ) -> HRESULT {
         // Desktop destroyed is not anymore in the stack
         eat_error(|| {
-            Ok((self.sender)(DesktopEvent::DesktopDestroyed {
-                destroyed: desktop_destroyed.try_into()?,
-                fallback: desktop_fallback.try_into()?,
-            }))
+            self.send(EventMask::DESKTOP_DESTROYED, || {
+                Ok(DesktopEvent::DesktopDestroyed {
+                    destroyed: desktop_destroyed.try_into()?,
+                    fallback: desktop_fallback.try_into()?,
+                })
+            })
         });
         HRESULT(0)
     }
@@ -267,11 +963,13 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
         new_index: i64,
     ) -> HRESULT {
         eat_error(|| {
-            Ok((self.sender)(DesktopEvent::DesktopMoved {
-                desktop: desktop.try_into()?,
-                old_index,
-                new_index,
-            }))
+            self.send(EventMask::DESKTOP_MOVED, || {
+                Ok(DesktopEvent::DesktopMoved {
+                    desktop: desktop.try_into()?,
+                    old_index,
+                    new_index,
+                })
+            })
         });
         HRESULT(0)
     }
@@ -282,26 +980,220 @@ impl IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
         name: HSTRING,
     ) -> HRESULT {
         eat_error(|| {
-            Ok((self.sender)(DesktopEvent::DesktopNameChanged(
-                desktop.try_into()?,
-                name.to_string(),
-            )))
+            self.send(EventMask::RENAMED, || {
+                Ok(DesktopEvent::DesktopNameChanged(
+                    desktop.try_into()?,
+                    name.to_string(),
+                ))
+            })
         });
         HRESULT(0)
     }
 
     unsafe fn view_virtual_desktop_changed(&self, view: ComIn<IApplicationView>) -> HRESULT {
-        let mut hwnd = HWND::default();
-        let _ = view.get_thumbnail_window(&mut hwnd);
-        (self.sender)(DesktopEvent::WindowChanged(hwnd));
+        eat_error(|| self.view_changed(&view));
         HRESULT(0)
     }
 
     unsafe fn virtual_desktop_switched(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_CHANGED, || {
+                Ok(DesktopEvent::DesktopSwitched(desktop.try_into()?))
+            })
+        });
         HRESULT(0)
     }
 
     unsafe fn remote_virtual_desktop_connected(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::REMOTE_DESKTOP, || {
+                Ok(DesktopEvent::RemoteDesktopConnected(desktop.try_into()?))
+            })
+        });
+        HRESULT(0)
+    }
+}
+
+// The events are the same as without the monitors parameter
+#[cfg(windows)]
+#[allow(unused_variables)]
+impl IVirtualDesktopNotificationMonitors_Impl for VirtualDesktopNotification {
+    unsafe fn virtual_desktop_created(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_created(self, desktop)
+    }
+
+    unsafe fn virtual_desktop_destroy_begin(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_destroy_begin(
+            self,
+            desktop_destroyed,
+            desktop_fallback,
+        )
+    }
+
+    unsafe fn virtual_desktop_destroy_failed(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_destroy_failed(
+            self,
+            desktop_destroyed,
+            desktop_fallback,
+        )
+    }
+
+    unsafe fn virtual_desktop_destroyed(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_destroyed: ComIn<IVirtualDesktop>,
+        desktop_fallback: ComIn<IVirtualDesktop>,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_destroyed(
+            self,
+            desktop_destroyed,
+            desktop_fallback,
+        )
+    }
+
+    unsafe fn virtual_desktop_is_per_monitor_changed(&self, is_per_monitor: i32) -> HRESULT {
+        HRESULT(0)
+    }
+
+    unsafe fn virtual_desktop_moved(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop: ComIn<IVirtualDesktop>,
+        old_index: i64,
+        new_index: i64,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_moved(self, desktop, old_index, new_index)
+    }
+
+    unsafe fn virtual_desktop_name_changed(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+        name: HSTRING,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_name_changed(self, desktop, name)
+    }
+
+    unsafe fn view_virtual_desktop_changed(&self, view: ComIn<IApplicationView>) -> HRESULT {
+        IVirtualDesktopNotification_Impl::view_virtual_desktop_changed(self, view)
+    }
+
+    unsafe fn current_virtual_desktop_changed(
+        &self,
+        monitors: ComIn<IObjectArray>,
+        desktop_old: ComIn<IVirtualDesktop>,
+        desktop_new: ComIn<IVirtualDesktop>,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::current_virtual_desktop_changed(
+            self,
+            desktop_old,
+            desktop_new,
+        )
+    }
+
+    unsafe fn virtual_desktop_wallpaper_changed(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+        name: HSTRING,
+    ) -> HRESULT {
+        IVirtualDesktopNotification_Impl::virtual_desktop_wallpaper_changed(self, desktop, name)
+    }
+}
+
+// Windows 10 has no monitors parameter and fewer events
+#[cfg(windows)]
+impl windows10::IVirtualDesktopNotification_Impl for VirtualDesktopNotification {
+    unsafe fn virtual_desktop_created(
+        &self,
+        desktop: ComIn<windows10::IVirtualDesktop>,
+    ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_CREATED, || {
+                Ok(DesktopEvent::DesktopCreated(desktop.try_into()?))
+            })
+        });
+        HRESULT(0)
+    }
+
+    unsafe fn virtual_desktop_destroy_begin(
+        &self,
+        desktop_destroyed: ComIn<windows10::IVirtualDesktop>,
+        desktop_fallback: ComIn<windows10::IVirtualDesktop>,
+    ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_DESTROYED, || {
+                Ok(DesktopEvent::DesktopDestroyBegin {
+                    destroyed: desktop_destroyed.try_into()?,
+                    fallback: desktop_fallback.try_into()?,
+                })
+            })
+        });
+        HRESULT(0)
+    }
+
+    unsafe fn virtual_desktop_destroy_failed(
+        &self,
+        desktop_destroyed: ComIn<windows10::IVirtualDesktop>,
+        desktop_fallback: ComIn<windows10::IVirtualDesktop>,
+    ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_DESTROYED, || {
+                Ok(DesktopEvent::DesktopDestroyFailed {
+                    destroyed: desktop_destroyed.try_into()?,
+                    fallback: desktop_fallback.try_into()?,
+                })
+            })
+        });
+        HRESULT(0)
+    }
+
+    unsafe fn virtual_desktop_destroyed(
+        &self,
+        desktop_destroyed: ComIn<windows10::IVirtualDesktop>,
+        desktop_fallback: ComIn<windows10::IVirtualDesktop>,
+    ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_DESTROYED, || {
+                Ok(DesktopEvent::DesktopDestroyed {
+                    destroyed: desktop_destroyed.try_into()?,
+                    fallback: desktop_fallback.try_into()?,
+                })
+            })
+        });
+        HRESULT(0)
+    }
+
+    unsafe fn view_virtual_desktop_changed(&self, view: ComIn<IApplicationView>) -> HRESULT {
+        eat_error(|| self.view_changed(&view));
+        HRESULT(0)
+    }
+
+    unsafe fn current_virtual_desktop_changed(
+        &self,
+        desktop_old: ComIn<windows10::IVirtualDesktop>,
+        desktop_new: ComIn<windows10::IVirtualDesktop>,
+    ) -> HRESULT {
+        eat_error(|| {
+            self.send(EventMask::DESKTOP_CHANGED, || {
+                Ok(DesktopEvent::DesktopChanged {
+                    old: desktop_old.try_into()?,
+                    new: desktop_new.try_into()?,
+                })
+            })
+        });
         HRESULT(0)
     }
 }
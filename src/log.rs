@@ -1,9 +1,9 @@
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, windows))]
 extern "system" {
     fn OutputDebugStringW(lpOutputString: windows::core::PCWSTR);
 }
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, windows))]
 pub(crate) fn log_output(s: &str) {
     unsafe {
         println!("{}", s);
@@ -13,6 +13,11 @@ pub(crate) fn log_output(s: &str) {
     }
 }
 
+#[cfg(all(debug_assertions, not(windows)))]
+pub(crate) fn log_output(s: &str) {
+    println!("{}", s);
+}
+
 #[cfg(not(debug_assertions))]
 #[inline]
 pub(crate) fn log_output(_s: &str) {}
@@ -0,0 +1,312 @@
+/// Retrying of the COM calls that fail with recoverable errors
+///
+/// After explorer.exe restarts the shell takes a few seconds to come back,
+/// the COM backend retries the calls with backoff as given by the
+/// `RetryPolicy`. The policy can be set process wide with `set_retry_policy`,
+/// or for the calls made inside `with_retry_policy`.
+use super::*;
+use std::cell::Cell;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+#[cfg(debug_assertions)]
+use crate::log::log_output;
+
+/// How the failed COM calls are retried
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use winvd::{set_retry_policy, RetryPolicy};
+///
+/// set_retry_policy(RetryPolicy {
+///     max_attempts: 10,
+///     deadline: Some(Duration::from_secs(15)),
+///     ..RetryPolicy::default()
+/// });
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts including the first one, 1 disables retrying
+    pub max_attempts: u32,
+
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+
+    /// The delay is multiplied by this after each retry
+    pub backoff_factor: u32,
+
+    /// Upper bound of the delay between attempts
+    pub max_delay: Duration,
+
+    /// No retry is started after this time from the first attempt
+    pub deadline: Option<Duration>,
+
+    /// Errors worth retrying, `Error::is_transient` by default
+    pub retryable: fn(&Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// Retries three times within two seconds
+    pub const DEFAULT: RetryPolicy = RetryPolicy {
+        max_attempts: 4,
+        initial_delay: Duration::from_millis(250),
+        backoff_factor: 2,
+        max_delay: Duration::from_secs(2),
+        deadline: Some(Duration::from_secs(5)),
+        retryable: Error::is_transient,
+    };
+
+    /// Fails on the first error
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        ..RetryPolicy::DEFAULT
+    };
+
+    /// Delay before the next attempt, `None` if the call should fail
+    ///
+    /// `attempts` is the number of attempts made so far, and `elapsed` the
+    /// time since the first attempt started.
+    pub fn next_delay(&self, attempts: u32, elapsed: Duration) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let factor = self
+            .backoff_factor
+            .checked_pow(attempts.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay);
+        match self.deadline {
+            Some(deadline) if elapsed + delay > deadline => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::DEFAULT
+    }
+}
+
+/// Result of the calls made inside `with_retry_policy`
+#[derive(Debug)]
+pub struct Retried<T> {
+    pub result: Result<T>,
+
+    /// 1 plus the number of retries of the COM calls
+    pub attempts: u32,
+}
+
+static RETRY_POLICY: RwLock<RetryPolicy> = RwLock::new(RetryPolicy::DEFAULT);
+
+thread_local! {
+    // Policy of `with_retry_policy`, and the retries made inside it
+    static SCOPED_POLICY: Cell<Option<RetryPolicy>> = const { Cell::new(None) };
+    static RETRIES: Cell<u32> = const { Cell::new(0) };
+
+    // Is a call being retried on this thread, nested calls are not retried
+    static RETRYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set the retry policy of all threads, `with_retry_policy` overrides it
+pub fn set_retry_policy(policy: RetryPolicy) {
+    *RETRY_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Get the retry policy set with `set_retry_policy`
+pub fn get_retry_policy() -> RetryPolicy {
+    *RETRY_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run the function with the retry policy, and report the attempts
+///
+/// The policy applies to the functions of this crate called inside, also when
+/// they are run on a `ComThread`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use winvd::{with_retry_policy, switch_desktop, RetryPolicy};
+///
+/// let retried = with_retry_policy(RetryPolicy::NONE, || switch_desktop(1));
+/// println!("{:?} after {} attempts", retried.result, retried.attempts);
+/// ```
+pub fn with_retry_policy<F, T>(policy: RetryPolicy, f: F) -> Retried<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let (result, retries) = run_scoped(policy, f);
+    record_retries(retries);
+    Retried {
+        result,
+        attempts: retries + 1,
+    }
+}
+
+/// Policy of the current call
+pub(crate) fn current_retry_policy() -> RetryPolicy {
+    SCOPED_POLICY
+        .with(|policy| policy.get())
+        .unwrap_or_else(get_retry_policy)
+}
+
+/// Runs the function with the policy on this thread, returns the retries made
+/// inside
+///
+/// Used also to carry the policy of the caller to a `ComThread`, the caller
+/// records the returned retries.
+pub(crate) fn run_scoped<F, T>(policy: RetryPolicy, f: F) -> (T, u32)
+where
+    F: FnOnce() -> T,
+{
+    // Outer scope is restored also if the function panics, e.g. on a
+    // `ComThread` which resumes the panic on the caller
+    struct Restore(Option<RetryPolicy>, u32);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_POLICY.with(|scoped| scoped.set(self.0));
+            RETRIES.with(|retries| retries.set(self.1));
+        }
+    }
+
+    let _restore = Restore(
+        SCOPED_POLICY.with(|scoped| scoped.replace(Some(policy))),
+        RETRIES.with(|retries| retries.replace(0)),
+    );
+    let value = f();
+    (value, RETRIES.with(|retries| retries.get()))
+}
+
+/// Runs the function, and retries it with the current policy
+///
+/// `reset` is called with the error before each retry. Calls nested inside
+/// the function are run only once, so that the policy applies once per public
+/// call and the attempts do not multiply.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn retry<F, R, T>(f: F, reset: R) -> Result<T>
+where
+    F: Fn() -> Result<T>,
+    R: Fn(&Error),
+{
+    if RETRYING.with(|retrying| retrying.get()) {
+        return f();
+    }
+
+    // Cleared also if the function panics
+    struct Retrying;
+    impl Drop for Retrying {
+        fn drop(&mut self) {
+            RETRYING.with(|retrying| retrying.set(false));
+        }
+    }
+    RETRYING.with(|retrying| retrying.set(true));
+    let _retrying = Retrying;
+
+    let policy = current_retry_policy();
+    let start = Instant::now();
+    let mut attempts = 1;
+    let mut value = f();
+    while let Err(er) = &value {
+        if !(policy.retryable)(er) {
+            break;
+        }
+        let Some(delay) = policy.next_delay(attempts, start.elapsed()) else {
+            break;
+        };
+
+        #[cfg(debug_assertions)]
+        log_output(&format!("Retry the function in {:?} after {:?}", delay, er));
+
+        reset(er);
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+        attempts += 1;
+        record_retries(1);
+        value = f();
+    }
+    value
+}
+
+/// Counts the retries for the enclosing `with_retry_policy`
+pub(crate) fn record_retries(count: u32) {
+    RETRIES.with(|retries| retries.set(retries.get().saturating_add(count)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+            backoff_factor: 3,
+            max_delay: Duration::from_millis(500),
+            deadline: Some(Duration::from_secs(1)),
+            ..RetryPolicy::DEFAULT
+        };
+        let delay =
+            |attempts, elapsed_ms| policy.next_delay(attempts, Duration::from_millis(elapsed_ms));
+        assert_eq!(delay(1, 0), Some(Duration::from_millis(100)));
+        assert_eq!(delay(2, 100), Some(Duration::from_millis(300)));
+        assert_eq!(delay(3, 400), Some(Duration::from_millis(500)));
+
+        // Deadline would pass during the delay
+        assert_eq!(delay(4, 600), None);
+        assert_eq!(delay(5, 0), None);
+        assert_eq!(RetryPolicy::NONE.next_delay(1, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_with_retry_policy() {
+        let retried = with_retry_policy(RetryPolicy::NONE, || {
+            assert_eq!(current_retry_policy().max_attempts, 1);
+            record_retries(2);
+
+            // Retries of the nested calls are counted also by the outer call
+            let inner = with_retry_policy(RetryPolicy::DEFAULT, || {
+                assert_eq!(current_retry_policy().max_attempts, 4);
+                record_retries(1);
+                Ok(())
+            });
+            assert_eq!(inner.attempts, 2);
+            assert_eq!(current_retry_policy().max_attempts, 1);
+            Err::<(), _>(Error::RpcServerNotAvailable)
+        });
+        assert_eq!(retried.result, Err(Error::RpcServerNotAvailable));
+        assert_eq!(retried.attempts, 4);
+    }
+
+    #[test]
+    fn test_nested_retry() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::ZERO,
+            ..RetryPolicy::DEFAULT
+        };
+        let calls = Cell::new(0);
+        let resets = Cell::new(0);
+        let retried = with_retry_policy(policy, || {
+            retry(
+                || {
+                    calls.set(calls.get() + 1);
+                    retry(|| Err::<(), _>(Error::RpcServerNotAvailable), |_| panic!())
+                },
+                |_| resets.set(resets.get() + 1),
+            )
+        });
+
+        // Only the outer call is retried
+        assert_eq!(retried.result, Err(Error::RpcServerNotAvailable));
+        assert_eq!(retried.attempts, 4);
+        assert_eq!(calls.get(), 4);
+        assert_eq!(resets.get(), 3);
+        assert!(!RETRYING.with(|retrying| retrying.get()));
+    }
+}
@@ -0,0 +1,1757 @@
+/// In-memory simulation of the virtual desktop shell
+///
+/// Desktops, windows and pins are kept in memory and the same `DesktopEvent`s
+/// are sent as the real `IVirtualDesktopNotification` would send. This allows
+/// testing code using this crate without Windows 11, select it with
+/// `set_backend(Backend::Simulated(shell))`.
+use super::*;
+use crate::listener::{window_event, ViewPlacement};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use windows::{core::GUID, Win32::Foundation::HWND};
+
+// GUIDs of simulated desktops are generated from a counter, prefix is "SIMULATE"
+const SIMULATED_GUID_BASE: u128 = 0x5349_4d55_4c41_5445_0000_0000_0000_0000;
+
+// Handles of simulated windows start from here
+const SIMULATED_HWND_BASE: isize = 0x10000;
+
+struct SimulatedDesktop {
+    id: GUID,
+    name: String,
+    wallpaper: String,
+}
+
+struct SimulatedWindow {
+    hwnd: HWND,
+    app_id: String,
+    title: String,
+    desktop: GUID,
+    pinned: bool,
+    movable: bool,
+    last_activation: u64,
+}
+
+struct ShellState {
+    desktops: Vec<SimulatedDesktop>,
+    current: GUID,
+
+    // Topmost first, the topmost window on the current desktop has the focus
+    windows: Vec<SimulatedWindow>,
+    pinned_apps: HashSet<String>,
+    next_guid: u128,
+    next_hwnd: isize,
+    next_timestamp: u64,
+    capabilities: Capabilities,
+    connected: bool,
+}
+
+type EventCallback = Arc<dyn Fn(DesktopEvent) + Send + Sync>;
+
+struct Listeners {
+    next_cookie: u32,
+    senders: HashMap<u32, (EventMask, EventCallback)>,
+}
+
+/// Simulated virtual desktop shell
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use winvd::*;
+///
+/// let shell = Arc::new(SimulatedShell::with_desktops(2));
+/// let notepad = shell.create_window("Microsoft.Notepad");
+/// set_backend(Backend::Simulated(shell));
+///
+/// move_window_to_desktop(1, notepad).unwrap();
+/// assert_eq!(get_desktop_by_window(notepad).unwrap().get_index().unwrap(), 1);
+/// ```
+pub struct SimulatedShell {
+    state: Mutex<ShellState>,
+    listeners: Mutex<Listeners>,
+}
+
+impl Default for SimulatedShell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShellState {
+    fn new_guid(&mut self) -> GUID {
+        let guid = GUID::from_u128(SIMULATED_GUID_BASE + self.next_guid);
+        self.next_guid += 1;
+        guid
+    }
+
+    fn new_timestamp(&mut self) -> u64 {
+        let timestamp = self.next_timestamp;
+        self.next_timestamp += 1;
+        timestamp
+    }
+
+    fn desktop_position(&self, desktop: &Desktop) -> Result<usize> {
+        match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(index) if (index as usize) < self.desktops.len() => {
+                Ok(index as usize)
+            }
+            DesktopInternal::Index(_) => Err(Error::DesktopNotFound((*desktop).into())),
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => self
+                .desktops
+                .iter()
+                .position(|d| d.id == id)
+                .ok_or(Error::DesktopNotFound(ErrorTarget::DesktopId(id))),
+        }
+    }
+
+    fn desktop_mut(&mut self, desktop: &Desktop) -> Result<&mut SimulatedDesktop> {
+        let position = self.desktop_position(desktop)?;
+        Ok(&mut self.desktops[position])
+    }
+
+    fn window(&self, window: &Window) -> Result<&SimulatedWindow> {
+        self.windows
+            .iter()
+            .find(|w| w.hwnd == window.hwnd())
+            .ok_or(Error::WindowNotFound(window.hwnd()))
+    }
+
+    fn window_mut(&mut self, window: &Window) -> Result<&mut SimulatedWindow> {
+        self.windows
+            .iter_mut()
+            .find(|w| w.hwnd == window.hwnd())
+            .ok_or(Error::WindowNotFound(window.hwnd()))
+    }
+
+    fn is_pinned(&self, window: &SimulatedWindow) -> bool {
+        window.pinned || self.pinned_apps.contains(&window.app_id)
+    }
+
+    fn desktop_window(&self, window: &SimulatedWindow) -> DesktopWindow {
+        let is_pinned = self.is_pinned(window);
+        DesktopWindow {
+            window: window.hwnd.into(),
+            desktop: (!is_pinned).then(|| window.desktop.into()),
+            is_pinned,
+        }
+    }
+
+    fn placement(&self, window: &SimulatedWindow) -> ViewPlacement {
+        ViewPlacement::from(&self.desktop_window(window))
+    }
+
+    /// Events for the window whose view had `old` placement before the change
+    fn window_changed(
+        &self,
+        events: &mut Vec<DesktopEvent>,
+        window: Window,
+        old: Option<ViewPlacement>,
+    ) {
+        events.push(DesktopEvent::WindowChanged(window));
+        if let Ok(simulated) = self.window(&window) {
+            events.extend(window_event(window, old, self.placement(simulated)));
+        }
+    }
+
+    /// Fails with `NotSupported` unless the shell has the capability
+    fn require(&self, capability: fn(&Capabilities) -> bool) -> Result<()> {
+        if capability(&self.capabilities) {
+            Ok(())
+        } else {
+            Err(Error::NotSupported)
+        }
+    }
+
+    fn placements_of_app(&self, app_id: &str) -> Vec<(Window, ViewPlacement)> {
+        self.windows
+            .iter()
+            .filter(|w| w.app_id == app_id)
+            .map(|w| (w.hwnd.into(), self.placement(w)))
+            .collect()
+    }
+}
+
+impl SimulatedShell {
+    /// Create a shell with a single desktop
+    pub fn new() -> Self {
+        Self::with_desktops(1)
+    }
+
+    /// Create a shell with given amount of desktops, at least one desktop is
+    /// always created. The first desktop is the current desktop.
+    pub fn with_desktops(count: u32) -> Self {
+        let mut state = ShellState {
+            desktops: Vec::new(),
+            current: GUID::default(),
+            windows: Vec::new(),
+            pinned_apps: HashSet::new(),
+            next_guid: 1,
+            next_hwnd: SIMULATED_HWND_BASE,
+            next_timestamp: 1,
+            capabilities: Capabilities::ALL,
+            connected: true,
+        };
+        for _ in 0..count.max(1) {
+            let id = state.new_guid();
+            state.desktops.push(SimulatedDesktop {
+                id,
+                name: String::new(),
+                wallpaper: String::new(),
+            });
+        }
+        state.current = state.desktops[0].id;
+        SimulatedShell {
+            state: Mutex::new(state),
+            listeners: Mutex::new(Listeners {
+                next_cookie: 1,
+                senders: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Open a new window of the app on the current desktop
+    pub fn create_window(&self, app_id: &str) -> Window {
+        let mut events = Vec::new();
+        let mut state = self.lock_state();
+        let hwnd = HWND(state.next_hwnd);
+        state.next_hwnd += 1;
+        let desktop = state.current;
+        let last_activation = state.new_timestamp();
+        state.windows.insert(
+            0,
+            SimulatedWindow {
+                hwnd,
+                app_id: app_id.to_owned(),
+                title: String::new(),
+                desktop,
+                pinned: false,
+                movable: true,
+                last_activation,
+            },
+        );
+        state.window_changed(&mut events, hwnd.into(), None);
+        drop(state);
+        self.dispatch(events);
+        hwnd.into()
+    }
+
+    /// Close the window
+    pub fn close_window<W>(&self, window: W) -> Result<()>
+    where
+        W: Into<Window>,
+    {
+        let hwnd = window.into().hwnd();
+        let mut state = self.lock_state();
+        let before = state.windows.len();
+        state.windows.retain(|w| w.hwnd != hwnd);
+        if state.windows.len() == before {
+            return Err(Error::WindowNotFound(hwnd));
+        }
+        Ok(())
+    }
+
+    /// Set can the window be moved between desktops, windows are movable by
+    /// default
+    pub fn set_window_movable<W>(&self, window: W, movable: bool) -> Result<()>
+    where
+        W: Into<Window>,
+    {
+        self.lock_state().window_mut(&window.into())?.movable = movable;
+        Ok(())
+    }
+
+    /// Set the title of the window, titles are empty by default
+    pub fn set_window_title<W>(&self, window: W, title: &str) -> Result<()>
+    where
+        W: Into<Window>,
+    {
+        self.lock_state().window_mut(&window.into())?.title = title.to_owned();
+        Ok(())
+    }
+
+    /// Set the supported operations and events, e.g.
+    /// `Capabilities::WINDOWS_10` to simulate Windows 10. Everything is
+    /// supported by default.
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.lock_state().capabilities = capabilities;
+    }
+
+    /// Simulate explorer.exe stopping or coming back, listeners registered
+    /// before stopping are lost and registering fails until it's back. Other
+    /// calls keep working, so the desktops can be changed meanwhile.
+    pub fn set_connected(&self, connected: bool) {
+        let mut state = self.lock_state();
+        state.connected = connected;
+        if !connected {
+            let mut listeners = self.listeners.lock().unwrap_or_else(|e| e.into_inner());
+            listeners.senders.clear();
+        }
+    }
+
+    /// Number of registered listeners
+    #[cfg(all(test, not(feature = "integration-tests")))]
+    pub(crate) fn listener_count(&self) -> usize {
+        let listeners = self.listeners.lock().unwrap_or_else(|e| e.into_inner());
+        listeners.senders.len()
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, ShellState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&ShellState) -> Result<R>) -> Result<R> {
+        f(&self.lock_state())
+    }
+
+    /// Modifies the state, events are sent after the state is unlocked so that
+    /// listeners may call the shell.
+    fn update<R>(
+        &self,
+        f: impl FnOnce(&mut ShellState, &mut Vec<DesktopEvent>) -> Result<R>,
+    ) -> Result<R> {
+        let mut events = Vec::new();
+        let mut state = self.lock_state();
+        let result = f(&mut state, &mut events);
+        if !state.capabilities.desktop_events {
+            events.retain(|event| !is_desktop_event(event));
+        }
+        drop(state);
+        self.dispatch(events);
+        result
+    }
+
+    fn dispatch(&self, events: Vec<DesktopEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        // Called without the lock, so that listeners may register or
+        // unregister from the callbacks
+        let senders: Vec<(EventMask, EventCallback)> = {
+            let listeners = self.listeners.lock().unwrap_or_else(|e| e.into_inner());
+            listeners.senders.values().cloned().collect()
+        };
+        for event in events {
+            // Event is cloned only for the listeners wanting it
+            let kind = EventMask::of(&event);
+            for (mask, sender) in &senders {
+                if mask.contains(kind) {
+                    sender(event.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Events sent only by shells with `Capabilities::desktop_events`
+fn is_desktop_event(event: &DesktopEvent) -> bool {
+    matches!(
+        event,
+        DesktopEvent::DesktopMoved { .. }
+            | DesktopEvent::DesktopNameChanged(..)
+            | DesktopEvent::DesktopWallpaperChanged(..)
+            | DesktopEvent::DesktopSwitched(_)
+            | DesktopEvent::RemoteDesktopConnected(_)
+    )
+}
+
+impl VirtualDesktopBackend for SimulatedShell {
+    fn get_desktop_index(&self, desktop: &Desktop) -> Result<u32> {
+        self.read(|s| Ok(s.desktop_position(desktop)? as u32))
+    }
+
+    fn get_desktop_id(&self, desktop: &Desktop) -> Result<GUID> {
+        self.read(|s| Ok(s.desktops[s.desktop_position(desktop)?].id))
+    }
+
+    fn get_desktops(&self) -> Result<Vec<Desktop>> {
+        self.read(|s| {
+            Ok(s.desktops
+                .iter()
+                .enumerate()
+                .map(|(i, d)| DesktopInternal::IndexGuid(i as u32, d.id).into())
+                .collect())
+        })
+    }
+
+    fn get_desktop_count(&self) -> Result<u32> {
+        self.read(|s| Ok(s.desktops.len() as u32))
+    }
+
+    fn get_current_desktop(&self) -> Result<Desktop> {
+        self.read(|s| Ok(s.current.into()))
+    }
+
+    fn switch_desktop(&self, desktop: &Desktop) -> Result<()> {
+        self.update(|s, events| {
+            let new = s.desktops[s.desktop_position(desktop)?].id;
+            let old = s.current;
+            if new != old {
+                s.current = new;
+                events.push(DesktopEvent::DesktopChanged {
+                    new: new.into(),
+                    old: old.into(),
+                });
+                events.push(DesktopEvent::DesktopSwitched(new.into()));
+            }
+            Ok(())
+        })
+    }
+
+    fn create_desktop(&self) -> Result<Desktop> {
+        self.update(|s, events| {
+            let id = s.new_guid();
+            s.desktops.push(SimulatedDesktop {
+                id,
+                name: String::new(),
+                wallpaper: String::new(),
+            });
+            events.push(DesktopEvent::DesktopCreated(id.into()));
+            Ok(DesktopInternal::IndexGuid(s.desktops.len() as u32 - 1, id).into())
+        })
+    }
+
+    fn remove_desktop(&self, desktop: &Desktop, fallback_desktop: &Desktop) -> Result<()> {
+        self.update(|s, events| {
+            let position = s.desktop_position(desktop)?;
+            let fallback_position = s.desktop_position(fallback_desktop)?;
+            let destroyed = s.desktops[position].id;
+            let fallback = s.desktops[fallback_position].id;
+            events.push(DesktopEvent::DesktopDestroyBegin {
+                destroyed: destroyed.into(),
+                fallback: fallback.into(),
+            });
+            if position == fallback_position {
+                events.push(DesktopEvent::DesktopDestroyFailed {
+                    destroyed: destroyed.into(),
+                    fallback: fallback.into(),
+                });
+                return Err(Error::RemoveDesktopFailed);
+            }
+
+            // Shell switches away from the removed desktop first
+            if s.current == destroyed {
+                s.current = fallback;
+                events.push(DesktopEvent::DesktopChanged {
+                    new: fallback.into(),
+                    old: destroyed.into(),
+                });
+                events.push(DesktopEvent::DesktopSwitched(fallback.into()));
+            }
+
+            // Windows of the removed desktop are moved to the fallback
+            let moved = s
+                .windows
+                .iter()
+                .filter(|w| w.desktop == destroyed)
+                .map(|w| (Window::from(w.hwnd), s.placement(w)))
+                .collect::<Vec<_>>();
+            for (window, old) in moved {
+                s.window_mut(&window)?.desktop = fallback;
+                s.window_changed(events, window, Some(old));
+            }
+
+            s.desktops.remove(position);
+            events.push(DesktopEvent::DesktopDestroyed {
+                destroyed: destroyed.into(),
+                fallback: fallback.into(),
+            });
+            Ok(())
+        })
+    }
+
+    fn move_desktop(&self, desktop: &Desktop, index: u32) -> Result<()> {
+        self.update(|s, events| {
+            s.require(|c| c.move_desktop)?;
+            let position = s.desktop_position(desktop)?;
+            if index as usize >= s.desktops.len() {
+                return Err(Error::InvalidDesktopIndex(index));
+            }
+            if position != index as usize {
+                let moved = s.desktops.remove(position);
+                events.push(DesktopEvent::DesktopMoved {
+                    desktop: moved.id.into(),
+                    old_index: position as i64,
+                    new_index: index as i64,
+                });
+                s.desktops.insert(index as usize, moved);
+            }
+            Ok(())
+        })
+    }
+
+    fn get_adjacent_desktop(
+        &self,
+        desktop: &Desktop,
+        direction: Direction,
+    ) -> Result<Option<Desktop>> {
+        self.read(|s| {
+            let position = s.desktop_position(desktop)?;
+            let adjacent = match direction {
+                Direction::Left => position.checked_sub(1),
+                Direction::Right => Some(position + 1),
+            };
+            Ok(adjacent.and_then(|index| {
+                let id = s.desktops.get(index)?.id;
+                Some(DesktopInternal::IndexGuid(index as u32, id).into())
+            }))
+        })
+    }
+
+    fn get_desktop_name(&self, desktop: &Desktop) -> Result<String> {
+        self.read(|s| {
+            s.require(|c| c.desktop_names)?;
+            Ok(s.desktops[s.desktop_position(desktop)?].name.clone())
+        })
+    }
+
+    fn set_desktop_name(&self, desktop: &Desktop, name: &str) -> Result<()> {
+        self.update(|s, events| {
+            s.require(|c| c.desktop_names)?;
+            let desktop = s.desktop_mut(desktop)?;
+            desktop.name = name.to_owned();
+            events.push(DesktopEvent::DesktopNameChanged(
+                desktop.id.into(),
+                name.to_owned(),
+            ));
+            Ok(())
+        })
+    }
+
+    fn get_desktop_wallpaper(&self, desktop: &Desktop) -> Result<String> {
+        self.read(|s| {
+            s.require(|c| c.desktop_wallpapers)?;
+            Ok(s.desktops[s.desktop_position(desktop)?].wallpaper.clone())
+        })
+    }
+
+    fn set_desktop_wallpaper(&self, desktop: &Desktop, path: &str) -> Result<()> {
+        self.update(|s, events| {
+            s.require(|c| c.desktop_wallpapers)?;
+            let desktop = s.desktop_mut(desktop)?;
+            desktop.wallpaper = path.to_owned();
+            events.push(DesktopEvent::DesktopWallpaperChanged(
+                desktop.id.into(),
+                path.to_owned(),
+            ));
+            Ok(())
+        })
+    }
+
+    fn set_wallpaper_for_all(&self, path: &str) -> Result<()> {
+        self.update(|s, events| {
+            s.require(|c| c.desktop_wallpapers)?;
+            for desktop in s.desktops.iter_mut().filter(|d| d.wallpaper != path) {
+                desktop.wallpaper = path.to_owned();
+                events.push(DesktopEvent::DesktopWallpaperChanged(
+                    desktop.id.into(),
+                    path.to_owned(),
+                ));
+            }
+            Ok(())
+        })
+    }
+
+    fn is_window_on_desktop(&self, window: &Window, desktop: &Desktop) -> Result<bool> {
+        self.read(|s| {
+            let desktop = s.desktops[s.desktop_position(desktop)?].id;
+            Ok(s.window(window)?.desktop == desktop)
+        })
+    }
+
+    fn is_window_on_current_desktop(&self, window: &Window) -> Result<bool> {
+        self.read(|s| {
+            let window = s.window(window)?;
+            Ok(s.is_pinned(window) || window.desktop == s.current)
+        })
+    }
+
+    fn can_move_window(&self, window: &Window) -> Result<bool> {
+        self.read(|s| Ok(s.window(window)?.movable))
+    }
+
+    fn move_window_to_desktop(&self, window: &Window, desktop: &Desktop) -> Result<()> {
+        self.update(|s, events| {
+            let desktop = s.desktops[s.desktop_position(desktop)?].id;
+            let old = s.placement(s.window(window)?);
+            let moved = s.window_mut(window)?;
+            if !moved.movable {
+                return Err(Error::WindowNotMovable(window.hwnd()));
+            }
+            moved.desktop = desktop;
+            s.window_changed(events, *window, Some(old));
+            Ok(())
+        })
+    }
+
+    fn get_desktop_by_window(&self, window: &Window) -> Result<Desktop> {
+        self.read(|s| Ok(s.window(window)?.desktop.into()))
+    }
+
+    fn get_all_windows(&self) -> Result<Vec<DesktopWindow>> {
+        // Windows are kept topmost first, oldest window is listed first
+        let mut windows = self.get_all_windows_by_zorder()?;
+        windows.reverse();
+        Ok(windows)
+    }
+
+    fn get_all_windows_by_zorder(&self) -> Result<Vec<DesktopWindow>> {
+        self.read(|s| Ok(s.windows.iter().map(|w| s.desktop_window(w)).collect()))
+    }
+
+    fn get_focused_window(&self) -> Result<Option<DesktopWindow>> {
+        self.read(|s| {
+            Ok(s.windows
+                .iter()
+                .find(|w| s.is_pinned(w) || w.desktop == s.current)
+                .map(|w| s.desktop_window(w)))
+        })
+    }
+
+    fn get_last_active_window(&self, desktop: &Desktop) -> Result<Option<DesktopWindow>> {
+        self.read(|s| {
+            let id = s.desktops[s.desktop_position(desktop)?].id;
+            Ok(s.windows
+                .iter()
+                .find(|w| !s.is_pinned(w) && w.desktop == id)
+                .map(|w| s.desktop_window(w)))
+        })
+    }
+
+    fn get_window_title(&self, window: &Window) -> Result<String> {
+        self.read(|s| Ok(s.window(window)?.title.clone()))
+    }
+
+    fn get_window_info(&self, window: &Window) -> Result<WindowInfo> {
+        self.read(|s| {
+            let window = s.window(window)?;
+            let is_visible = s.is_pinned(window) || window.desktop == s.current;
+            Ok(WindowInfo {
+                window: window.hwnd.into(),
+                app_id: window.app_id.clone(),
+                last_activation_timestamp: window.last_activation,
+                show_in_switchers: true,
+                scale_factor: 100,
+                visibility: match is_visible {
+                    true => Visibility::Visible,
+                    false => Visibility::NotVisible,
+                },
+                can_receive_input: true,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn activate_window(&self, window: &Window) -> Result<()> {
+        self.update(|s, events| {
+            let position = s
+                .windows
+                .iter()
+                .position(|w| w.hwnd == window.hwnd())
+                .ok_or(Error::WindowNotFound(window.hwnd()))?;
+            let mut activated = s.windows.remove(position);
+            activated.last_activation = s.new_timestamp();
+            if !s.is_pinned(&activated) && activated.desktop != s.current {
+                events.push(DesktopEvent::DesktopChanged {
+                    new: activated.desktop.into(),
+                    old: s.current.into(),
+                });
+                events.push(DesktopEvent::DesktopSwitched(activated.desktop.into()));
+                s.current = activated.desktop;
+            }
+            s.windows.insert(0, activated);
+            Ok(())
+        })
+    }
+
+    fn is_pinned_window(&self, window: &Window) -> Result<bool> {
+        self.read(|s| Ok(s.window(window)?.pinned))
+    }
+
+    fn pin_window(&self, window: &Window) -> Result<()> {
+        self.update(|s, events| {
+            let old = s.placement(s.window(window)?);
+            s.window_mut(window)?.pinned = true;
+            s.window_changed(events, *window, Some(old));
+            Ok(())
+        })
+    }
+
+    fn unpin_window(&self, window: &Window) -> Result<()> {
+        self.update(|s, events| {
+            let old = s.placement(s.window(window)?);
+            s.window_mut(window)?.pinned = false;
+            s.window_changed(events, *window, Some(old));
+            Ok(())
+        })
+    }
+
+    fn is_pinned_app(&self, window: &Window) -> Result<bool> {
+        self.read(|s| Ok(s.pinned_apps.contains(&s.window(window)?.app_id)))
+    }
+
+    fn pin_app(&self, window: &Window) -> Result<()> {
+        let app_id = self.get_app_id(window)?;
+        self.pin_app_id(&app_id)
+    }
+
+    fn unpin_app(&self, window: &Window) -> Result<()> {
+        let app_id = self.get_app_id(window)?;
+        self.unpin_app_id(&app_id)
+    }
+
+    fn get_app_id(&self, window: &Window) -> Result<String> {
+        self.read(|s| Ok(s.window(window)?.app_id.clone()))
+    }
+
+    fn is_app_id_pinned(&self, app_id: &str) -> Result<bool> {
+        self.read(|s| Ok(s.pinned_apps.contains(app_id)))
+    }
+
+    fn pin_app_id(&self, app_id: &str) -> Result<()> {
+        self.update(|s, events| {
+            let old = s.placements_of_app(app_id);
+            s.pinned_apps.insert(app_id.to_owned());
+            for (window, old) in old {
+                s.window_changed(events, window, Some(old));
+            }
+            Ok(())
+        })
+    }
+
+    fn unpin_app_id(&self, app_id: &str) -> Result<()> {
+        self.update(|s, events| {
+            let old = s.placements_of_app(app_id);
+            s.pinned_apps.remove(app_id);
+            for (window, old) in old {
+                s.window_changed(events, window, Some(old));
+            }
+            Ok(())
+        })
+    }
+
+    fn get_windows_by_app_id(&self, app_id: &str) -> Result<Vec<DesktopWindow>> {
+        self.read(|s| {
+            Ok(s.windows
+                .iter()
+                .rev()
+                .filter(|w| w.app_id == app_id)
+                .map(|w| s.desktop_window(w))
+                .collect())
+        })
+    }
+
+    fn register_for_notifications(
+        &self,
+        sender: Box<dyn Fn(DesktopEvent) + Send + Sync>,
+        mask: EventMask,
+    ) -> Result<u32> {
+        if !self.is_connected() {
+            return Err(Error::RpcServerNotAvailable);
+        }
+        let mut listeners = self.listeners.lock().unwrap_or_else(|e| e.into_inner());
+        let cookie = listeners.next_cookie;
+        listeners.next_cookie += 1;
+        listeners.senders.insert(cookie, (mask, Arc::from(sender)));
+        Ok(cookie)
+    }
+
+    fn unregister_for_notifications(&self, cookie: u32) -> Result<()> {
+        let mut listeners = self.listeners.lock().unwrap_or_else(|e| e.into_inner());
+        listeners
+            .senders
+            .remove(&cookie)
+            .map(|_| ())
+            .ok_or(Error::ComElementNotFound)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.lock_state().connected
+    }
+
+    fn get_capabilities(&self) -> Result<Capabilities> {
+        self.read(|s| Ok(s.capabilities))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver};
+
+    fn listen(shell: &SimulatedShell) -> Receiver<DesktopEvent> {
+        let (tx, rx) = channel();
+        shell
+            .register_for_notifications(
+                Box::new(move |e| {
+                    let _ = tx.send(e);
+                }),
+                EventMask::ALL,
+            )
+            .unwrap();
+        rx
+    }
+
+    #[test]
+    fn test_simulated_listener_unregisters_in_callback() {
+        let shell = Arc::new(SimulatedShell::with_desktops(2));
+        let (tx, rx) = channel();
+        let cookie = Arc::new(Mutex::new(None::<u32>));
+        let callback_shell = Arc::downgrade(&shell);
+        let callback_cookie = cookie.clone();
+        let registered = shell
+            .register_for_notifications(
+                Box::new(move |e| {
+                    let _ = tx.send(e);
+                    let cookie = callback_cookie.lock().unwrap().take();
+                    if let (Some(shell), Some(cookie)) = (callback_shell.upgrade(), cookie) {
+                        shell.unregister_for_notifications(cookie).unwrap();
+                    }
+                }),
+                EventMask::ALL,
+            )
+            .unwrap();
+        *cookie.lock().unwrap() = Some(registered);
+
+        // Events of the same change are still sent, later ones are not
+        shell.switch_desktop(&1.into()).unwrap();
+        assert_eq!(shell.listener_count(), 0);
+        assert_eq!(rx.try_iter().count(), 2);
+        shell.switch_desktop(&0.into()).unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_simulated_desktops() {
+        let shell = SimulatedShell::with_desktops(2);
+        let rx = listen(&shell);
+
+        let first = shell.get_desktops().unwrap()[0];
+        let created = shell.create_desktop().unwrap();
+        let first_id: Desktop = shell.get_desktop_id(&first).unwrap().into();
+        let created_id: Desktop = shell.get_desktop_id(&created).unwrap().into();
+        assert_eq!(shell.get_desktop_count().unwrap(), 3);
+        assert_eq!(shell.get_desktop_index(&created).unwrap(), 2);
+
+        shell.switch_desktop(&created).unwrap();
+        shell.set_desktop_name(&created, "Work").unwrap();
+        assert_eq!(shell.get_desktop_name(&2.into()).unwrap(), "Work");
+
+        // Removing the current desktop switches to the fallback
+        shell.remove_desktop(&created, &first).unwrap();
+        assert_eq!(shell.get_desktop_count().unwrap(), 2);
+        assert_eq!(
+            shell.get_current_desktop().unwrap(),
+            shell.get_desktops().unwrap()[0]
+        );
+
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                DesktopEvent::DesktopCreated(created_id),
+                DesktopEvent::DesktopChanged {
+                    new: created_id,
+                    old: first_id
+                },
+                DesktopEvent::DesktopSwitched(created_id),
+                DesktopEvent::DesktopNameChanged(created_id, "Work".to_owned()),
+                DesktopEvent::DesktopDestroyBegin {
+                    destroyed: created_id,
+                    fallback: first_id
+                },
+                DesktopEvent::DesktopChanged {
+                    new: first_id,
+                    old: created_id
+                },
+                DesktopEvent::DesktopSwitched(first_id),
+                DesktopEvent::DesktopDestroyed {
+                    destroyed: created_id,
+                    fallback: first_id
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simulated_errors() {
+        let shell = SimulatedShell::new();
+        let rx = listen(&shell);
+        assert_eq!(
+            shell.switch_desktop(&5.into()).unwrap_err(),
+            Error::DesktopNotFound(ErrorTarget::DesktopIndex(5))
+        );
+        assert_eq!(
+            shell.remove_desktop(&0.into(), &0.into()).unwrap_err(),
+            Error::RemoveDesktopFailed
+        );
+        let first: Desktop = shell.get_desktop_id(&0.into()).unwrap().into();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![
+                DesktopEvent::DesktopDestroyBegin {
+                    destroyed: first,
+                    fallback: first
+                },
+                DesktopEvent::DesktopDestroyFailed {
+                    destroyed: first,
+                    fallback: first
+                },
+            ]
+        );
+        assert_eq!(
+            shell.get_desktop_by_window(&HWND(1).into()).unwrap_err(),
+            Error::WindowNotFound(HWND(1))
+        );
+    }
+
+    #[test]
+    fn test_simulated_windows() {
+        let shell = SimulatedShell::with_desktops(3);
+        let notepad = shell.create_window("Microsoft.Notepad");
+        let other_notepad = shell.create_window("Microsoft.Notepad");
+        let rx = listen(&shell);
+
+        shell.move_window_to_desktop(&notepad, &2.into()).unwrap();
+        assert!(shell.is_window_on_desktop(&notepad, &2.into()).unwrap());
+        assert!(!shell.is_window_on_current_desktop(&notepad).unwrap());
+
+        shell.pin_window(&notepad).unwrap();
+        assert!(shell.is_pinned_window(&notepad).unwrap());
+        assert!(shell.is_window_on_current_desktop(&notepad).unwrap());
+
+        shell.pin_app(&other_notepad).unwrap();
+        assert!(shell.is_pinned_app(&notepad).unwrap());
+
+        shell.close_window(notepad).unwrap();
+        assert_eq!(
+            shell.is_pinned_window(&notepad).unwrap_err(),
+            Error::WindowNotFound(notepad.hwnd())
+        );
+
+        let first: Desktop = shell.get_desktop_id(&0.into()).unwrap().into();
+        let third: Desktop = shell.get_desktop_id(&2.into()).unwrap().into();
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                DesktopEvent::WindowChanged(notepad),
+                DesktopEvent::WindowMovedToDesktop {
+                    window: notepad,
+                    from: first,
+                    to: third
+                },
+                DesktopEvent::WindowChanged(notepad),
+                DesktopEvent::WindowPinned {
+                    window: notepad,
+                    from: third
+                },
+                // Already pinned window gets no pinned event from the app pin
+                DesktopEvent::WindowChanged(other_notepad),
+                DesktopEvent::WindowPinned {
+                    window: other_notepad,
+                    from: first
+                },
+                DesktopEvent::WindowChanged(notepad),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simulated_move_desktop() {
+        let shell = SimulatedShell::with_desktops(3);
+        let rx = listen(&shell);
+        let first = shell.get_desktop_id(&0.into()).unwrap();
+
+        shell.move_desktop(&0.into(), 2).unwrap();
+        assert_eq!(shell.get_desktop_index(&first.into()).unwrap(), 2);
+        assert_eq!(
+            shell.move_desktop(&0.into(), 3).unwrap_err(),
+            Error::InvalidDesktopIndex(3)
+        );
+
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![DesktopEvent::DesktopMoved {
+                desktop: first.into(),
+                old_index: 0,
+                new_index: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_simulated_adjacent_desktop() {
+        let shell = SimulatedShell::with_desktops(3);
+        let second = shell.get_desktop_id(&1.into()).unwrap();
+
+        let right = shell
+            .get_adjacent_desktop(&0.into(), Direction::Right)
+            .unwrap();
+        assert_eq!(shell.get_desktop_id(&right.unwrap()).unwrap(), second);
+        let left = shell
+            .get_adjacent_desktop(&2.into(), Direction::Left)
+            .unwrap();
+        assert_eq!(shell.get_desktop_id(&left.unwrap()).unwrap(), second);
+
+        assert_eq!(
+            shell.get_adjacent_desktop(&0.into(), Direction::Left),
+            Ok(None)
+        );
+        assert_eq!(
+            shell.get_adjacent_desktop(&2.into(), Direction::Right),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_simulated_all_windows() {
+        let shell = SimulatedShell::with_desktops(2);
+        let first = shell.get_desktop_id(&0.into()).unwrap();
+        let notepad = shell.create_window("Microsoft.Notepad");
+        let explorer = shell.create_window("Microsoft.Explorer");
+        let paint = shell.create_window("Microsoft.Paint");
+        shell.pin_window(&explorer).unwrap();
+
+        let windows = shell.get_all_windows().unwrap();
+        assert_eq!(
+            windows,
+            vec![
+                DesktopWindow {
+                    window: notepad,
+                    desktop: Some(first.into()),
+                    is_pinned: false,
+                },
+                DesktopWindow {
+                    window: explorer,
+                    desktop: None,
+                    is_pinned: true,
+                },
+                DesktopWindow {
+                    window: paint,
+                    desktop: Some(first.into()),
+                    is_pinned: false,
+                },
+            ]
+        );
+        let zorder = shell.get_all_windows_by_zorder().unwrap();
+        assert_eq!(zorder[0].window, paint);
+        assert_eq!(zorder[2].window, notepad);
+    }
+
+    #[test]
+    fn test_simulated_focused_window() {
+        let shell = SimulatedShell::with_desktops(2);
+        let second = shell.get_desktop_id(&1.into()).unwrap();
+        assert_eq!(shell.get_focused_window().unwrap(), None);
+
+        let notepad = shell.create_window("Microsoft.Notepad");
+        let paint = shell.create_window("Microsoft.Paint");
+        assert_eq!(shell.get_focused_window().unwrap().unwrap().window, paint);
+
+        // Focus moves to the next window on the current desktop
+        shell.move_window_to_desktop(&paint, &1.into()).unwrap();
+        assert_eq!(shell.get_focused_window().unwrap().unwrap().window, notepad);
+
+        let last = shell.get_last_active_window(&1.into()).unwrap().unwrap();
+        assert_eq!(last.window, paint);
+        assert_eq!(last.desktop, Some(second.into()));
+
+        shell.pin_window(&notepad).unwrap();
+        assert_eq!(shell.get_last_active_window(&0.into()).unwrap(), None);
+        assert_eq!(shell.get_focused_window().unwrap().unwrap().window, notepad);
+    }
+
+    #[test]
+    fn test_simulated_activate_window() {
+        let shell = SimulatedShell::with_desktops(2);
+        let first = shell.get_desktop_id(&0.into()).unwrap();
+        let second = shell.get_desktop_id(&1.into()).unwrap();
+        let notepad = shell.create_window("Microsoft.Notepad");
+        let paint = shell.create_window("Microsoft.Paint");
+        shell.move_window_to_desktop(&notepad, &1.into()).unwrap();
+        let rx = listen(&shell);
+
+        shell.activate_window(&notepad).unwrap();
+        assert_eq!(shell.get_focused_window().unwrap().unwrap().window, notepad);
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![
+                DesktopEvent::DesktopChanged {
+                    new: second.into(),
+                    old: first.into()
+                },
+                DesktopEvent::DesktopSwitched(second.into())
+            ]
+        );
+
+        // Pinned window is activated on the current desktop
+        shell.pin_window(&paint).unwrap();
+        shell.activate_window(&paint).unwrap();
+        assert_eq!(shell.get_focused_window().unwrap().unwrap().window, paint);
+        assert_eq!(shell.get_current_desktop().unwrap(), second.into());
+
+        assert_eq!(
+            shell.activate_window(&HWND(1).into()),
+            Err(Error::WindowNotFound(HWND(1)))
+        );
+    }
+
+    #[test]
+    fn test_simulated_unmovable_window() {
+        let shell = SimulatedShell::with_desktops(2);
+        let notepad = shell.create_window("Microsoft.Notepad");
+        let settings = shell.create_window("Windows.ImmersiveControlPanel");
+        shell.set_window_movable(settings, false).unwrap();
+        let rx = listen(&shell);
+
+        assert!(shell.can_move_window(&notepad).unwrap());
+        assert!(!shell.can_move_window(&settings).unwrap());
+        assert_eq!(
+            shell.move_window_to_desktop(&settings, &1.into()),
+            Err(Error::WindowNotMovable(settings.hwnd()))
+        );
+        assert_eq!(
+            shell.get_desktop_index(&shell.get_desktop_by_window(&settings).unwrap()),
+            Ok(0)
+        );
+        assert_eq!(rx.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_simulated_window_events() {
+        let shell = SimulatedShell::with_desktops(2);
+        let rx = listen(&shell);
+        let first: Desktop = shell.get_desktop_id(&0.into()).unwrap().into();
+        let second: Desktop = shell.get_desktop_id(&1.into()).unwrap().into();
+
+        let notepad = shell.create_window("Microsoft.Notepad");
+        shell.pin_app(&notepad).unwrap();
+        let pinned_notepad = shell.create_window("Microsoft.Notepad");
+        shell.unpin_app(&notepad).unwrap();
+        shell.move_window_to_desktop(&notepad, &second).unwrap();
+
+        // Removing the desktop moves its windows to the fallback
+        shell.remove_desktop(&second, &first).unwrap();
+
+        let window_events = rx
+            .try_iter()
+            .filter(|e| !matches!(e, DesktopEvent::WindowChanged(_)))
+            .filter(|e| !matches!(e, DesktopEvent::DesktopDestroyBegin { .. }))
+            .filter(|e| !matches!(e, DesktopEvent::DesktopDestroyed { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            window_events,
+            vec![
+                DesktopEvent::WindowAppeared {
+                    window: notepad,
+                    desktop: Some(first)
+                },
+                DesktopEvent::WindowPinned {
+                    window: notepad,
+                    from: first
+                },
+                DesktopEvent::WindowAppeared {
+                    window: pinned_notepad,
+                    desktop: None
+                },
+                DesktopEvent::WindowUnpinned {
+                    window: pinned_notepad,
+                    to: first
+                },
+                DesktopEvent::WindowUnpinned {
+                    window: notepad,
+                    to: first
+                },
+                DesktopEvent::WindowMovedToDesktop {
+                    window: notepad,
+                    from: first,
+                    to: second
+                },
+                DesktopEvent::WindowMovedToDesktop {
+                    window: notepad,
+                    from: second,
+                    to: first
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simulated_app_id() {
+        let shell = SimulatedShell::with_desktops(2);
+        let notepad = shell.create_window("Microsoft.Notepad");
+        let paint = shell.create_window("Microsoft.Paint");
+        let notepad2 = shell.create_window("Microsoft.Notepad");
+        assert_eq!(shell.get_app_id(&notepad).unwrap(), "Microsoft.Notepad");
+
+        // Pin before any windows of the app exist
+        shell.pin_app_id("Microsoft.Calculator").unwrap();
+        assert!(shell.is_app_id_pinned("Microsoft.Calculator").unwrap());
+        let calculator = shell.create_window("Microsoft.Calculator");
+        assert!(shell.is_pinned_app(&calculator).unwrap());
+        shell.unpin_app_id("Microsoft.Calculator").unwrap();
+        assert!(!shell.is_pinned_app(&calculator).unwrap());
+
+        let rx = listen(&shell);
+        shell.pin_app_id("Microsoft.Notepad").unwrap();
+        assert!(shell.is_pinned_app(&notepad2).unwrap());
+        assert!(!shell.is_pinned_app(&paint).unwrap());
+        let pinned = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                DesktopEvent::WindowPinned { window, .. } => Some(window),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(pinned, vec![notepad2, notepad]);
+
+        let windows = shell.get_windows_by_app_id("Microsoft.Notepad").unwrap();
+        let app_windows = windows.iter().map(|w| w.window).collect::<Vec<_>>();
+        assert_eq!(app_windows, vec![notepad, notepad2]);
+        assert!(windows.iter().all(|w| w.is_pinned));
+    }
+
+    #[test]
+    fn test_simulated_windows_10_capabilities() {
+        let shell = SimulatedShell::with_desktops(2);
+        assert_eq!(shell.get_capabilities(), Ok(Capabilities::ALL));
+        shell.set_capabilities(Capabilities::WINDOWS_10);
+        assert_eq!(shell.get_capabilities(), Ok(Capabilities::WINDOWS_10));
+        let rx = listen(&shell);
+
+        let first = Desktop::from(0);
+        assert_eq!(
+            shell.move_desktop(&first, 1).unwrap_err(),
+            Error::NotSupported
+        );
+        assert_eq!(
+            shell.get_desktop_wallpaper(&first).unwrap_err(),
+            Error::NotSupported
+        );
+        assert_eq!(
+            shell
+                .set_wallpaper_for_all("C:\\wallpaper.jpg")
+                .unwrap_err(),
+            Error::NotSupported
+        );
+
+        // Names work, but only the events of Windows 10 are sent
+        shell.set_desktop_name(&first, "Mail").unwrap();
+        assert_eq!(shell.get_desktop_name(&first).unwrap(), "Mail");
+        shell.switch_desktop(&1.into()).unwrap();
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], DesktopEvent::DesktopChanged { .. }));
+    }
+
+    // Selecting the backend is process wide, integration tests need the COM
+    // backend
+    #[cfg(not(feature = "integration-tests"))]
+    mod global_backend {
+        use super::*;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        static GLOBAL_BACKEND: Mutex<()> = Mutex::new(());
+
+        /// Runs the test with the simulated shell as the process wide backend
+        fn with_simulated_backend(desktops: u32, test: impl FnOnce(Arc<SimulatedShell>)) {
+            let _lock = GLOBAL_BACKEND.lock().unwrap_or_else(|e| e.into_inner());
+            let shell = Arc::new(SimulatedShell::with_desktops(desktops));
+            set_backend(Backend::Simulated(shell.clone()));
+            test(shell);
+            set_backend(Backend::Com);
+        }
+
+        #[test]
+        fn test_simulated_backend_listener() {
+            with_simulated_backend(2, |_| {
+                let (tx, rx) = channel::<DesktopEvent>();
+                let mut listener = listen_desktop_events(tx).unwrap();
+
+                switch_desktop(1).unwrap();
+                assert_eq!(get_current_desktop().unwrap().get_index().unwrap(), 1);
+                get_desktop(0).set_wallpaper("C:\\wallpaper.jpg").unwrap();
+
+                let event = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+                assert_eq!(
+                    event,
+                    DesktopEvent::DesktopChanged {
+                        new: get_desktop(1),
+                        old: get_desktop(0)
+                    }
+                );
+                let event = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+                assert_eq!(event, DesktopEvent::DesktopSwitched(get_desktop(1)));
+                let event = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+                assert_eq!(
+                    event,
+                    DesktopEvent::DesktopWallpaperChanged(
+                        get_desktop(0),
+                        "C:\\wallpaper.jpg".to_owned()
+                    )
+                );
+
+                // Stopping the listener unregisters it
+                listener.stop().unwrap();
+                switch_desktop(0).unwrap();
+                assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+            });
+        }
+
+        #[test]
+        fn test_simulated_backend_listener_builder() {
+            with_simulated_backend(2, |shell| {
+                let (tx, rx) = channel::<DesktopEvent>();
+                let (name_tx, name_rx) = channel::<Option<String>>();
+                let _listener = ListenerBuilder::new()
+                    .interval(Duration::from_millis(50))
+                    .name("winvd-test-listener")
+                    .priority(ThreadPriority::Normal)
+                    .message_pump(true)
+                    .filter(move |event| {
+                        if event == &DesktopEvent::ShellDisconnected {
+                            let name = std::thread::current().name().map(str::to_owned);
+                            let _ = name_tx.send(name);
+                        }
+                        !matches!(
+                            event,
+                            DesktopEvent::WindowChanged(_)
+                                | DesktopEvent::ListenerRegistrationFailed { .. }
+                        )
+                    })
+                    .spawn(tx)
+                    .unwrap();
+                let recv = || rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+                let window = shell.create_window("Microsoft.Notepad");
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::WindowAppeared {
+                        window,
+                        desktop: Some(get_desktop(0))
+                    }
+                );
+
+                // Shell events are sent from the named listener thread
+                shell.set_connected(false);
+                assert_eq!(recv(), DesktopEvent::ShellDisconnected);
+                assert_eq!(
+                    name_rx.recv().unwrap().as_deref(),
+                    Some("winvd-test-listener")
+                );
+                shell.set_connected(true);
+                assert_eq!(recv(), DesktopEvent::ShellReconnected);
+            });
+        }
+
+        #[test]
+        fn test_simulated_backend_listener_filtered() {
+            with_simulated_backend(2, |shell| {
+                let notepad = shell.create_window("Microsoft.Notepad");
+                let terminal = shell.create_window("Microsoft.WindowsTerminal");
+
+                let (tx, rx) = channel::<DesktopEvent>();
+                let _desktops = listen_desktop_events_filtered(
+                    tx,
+                    EventMask::DESKTOP_CHANGED | EventMask::RENAMED,
+                )
+                .unwrap();
+
+                // Windows of one app
+                let (app_tx, app_rx) = channel::<DesktopEvent>();
+                let _notepad = ListenerBuilder::new()
+                    .mask(EventMask::WINDOW_MOVED)
+                    .filter(|event| {
+                        event.window().is_some_and(|window| {
+                            get_app_id(window).is_ok_and(|id| id == "Microsoft.Notepad")
+                        })
+                    })
+                    .spawn(app_tx)
+                    .unwrap();
+
+                move_window_to_desktop(1, terminal).unwrap();
+                move_window_to_desktop(1, notepad).unwrap();
+                get_desktop(1).set_name("Code").unwrap();
+                switch_desktop(1).unwrap();
+
+                let recv =
+                    |rx: &Receiver<DesktopEvent>| rx.recv_timeout(Duration::from_secs(1)).unwrap();
+                assert_eq!(
+                    recv(&rx),
+                    DesktopEvent::DesktopNameChanged(get_desktop(1), "Code".to_owned())
+                );
+                assert!(matches!(recv(&rx), DesktopEvent::DesktopChanged { .. }));
+                assert_eq!(recv(&rx), DesktopEvent::DesktopSwitched(get_desktop(1)));
+                assert!(rx.try_recv().is_err());
+
+                assert_eq!(
+                    recv(&app_rx),
+                    DesktopEvent::WindowMovedToDesktop {
+                        window: notepad,
+                        from: get_desktop(0),
+                        to: get_desktop(1)
+                    }
+                );
+                assert!(app_rx.try_recv().is_err());
+            });
+        }
+
+        #[test]
+        fn test_simulated_backend_listener_reconnects() {
+            with_simulated_backend(3, |shell| {
+                let ids: Vec<GUID> = (0..3)
+                    .map(|index| get_desktop(index).get_id().unwrap())
+                    .collect();
+                get_desktop(1).set_name("Mail").unwrap();
+                let (tx, rx) = channel::<DesktopEvent>();
+                let _listener = ListenerBuilder::new()
+                    .interval(Duration::from_millis(50))
+                    .spawn(tx)
+                    .unwrap();
+                let recv = || rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+                shell.set_connected(false);
+                assert_eq!(recv(), DesktopEvent::ShellDisconnected);
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::ListenerRegistrationFailed {
+                        error: Error::RpcServerNotAvailable
+                    }
+                );
+
+                // Changes while disconnected are not sent
+                let created = create_desktop().unwrap().get_id().unwrap();
+                get_desktop(1).set_name("Code").unwrap();
+                remove_desktop(ids[2], ids[0]).unwrap();
+                switch_desktop(created).unwrap();
+                shell.set_connected(true);
+
+                let mut event = recv();
+                while matches!(event, DesktopEvent::ListenerRegistrationFailed { .. }) {
+                    event = recv();
+                }
+                assert_eq!(event, DesktopEvent::ShellReconnected);
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::DesktopDestroyed {
+                        destroyed: ids[2].into(),
+                        fallback: created.into()
+                    }
+                );
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::DesktopNameChanged(ids[1].into(), "Code".to_owned())
+                );
+                assert_eq!(recv(), DesktopEvent::DesktopCreated(created.into()));
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::DesktopChanged {
+                        new: created.into(),
+                        old: ids[0].into()
+                    }
+                );
+
+                // Events are sent again
+                get_desktop(0).set_name("Web").unwrap();
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::DesktopNameChanged(ids[0].into(), "Web".to_owned())
+                );
+
+                // Desktops are kept up to date from the events, nothing is
+                // missed by the next reconnect
+                shell.set_connected(false);
+                assert_eq!(recv(), DesktopEvent::ShellDisconnected);
+                shell.set_connected(true);
+                let mut event = recv();
+                while matches!(event, DesktopEvent::ListenerRegistrationFailed { .. }) {
+                    event = recv();
+                }
+                assert_eq!(event, DesktopEvent::ShellReconnected);
+                assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+            });
+        }
+
+        #[test]
+        fn test_simulated_backend_listener_first_registration_fails() {
+            with_simulated_backend(2, |shell| {
+                shell.set_connected(false);
+                let (tx, rx) = channel::<DesktopEvent>();
+                let _listener = ListenerBuilder::new()
+                    .interval(Duration::from_millis(50))
+                    .spawn(tx)
+                    .unwrap();
+                let recv = || rx.recv_timeout(Duration::from_secs(1)).unwrap();
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::ListenerRegistrationFailed {
+                        error: Error::RpcServerNotAvailable
+                    }
+                );
+
+                // Nothing was connected before, so no reconnect and no diff
+                shell.set_connected(true);
+                while let Ok(event) = rx.recv_timeout(Duration::from_millis(200)) {
+                    assert!(matches!(
+                        event,
+                        DesktopEvent::ListenerRegistrationFailed { .. }
+                    ));
+                }
+                get_desktop(1).set_name("Code").unwrap();
+                assert_eq!(
+                    recv(),
+                    DesktopEvent::DesktopNameChanged(get_desktop(1), "Code".to_owned())
+                );
+            });
+        }
+
+        #[cfg(feature = "async")]
+        #[test]
+        fn test_simulated_backend_event_stream() {
+            use futures::{executor::block_on, StreamExt};
+
+            with_simulated_backend(2, |_| {
+                let mut events = listen_desktop_events_stream().unwrap();
+                switch_desktop(1).unwrap();
+                assert_eq!(
+                    block_on(events.next()),
+                    Some(DesktopEvent::DesktopChanged {
+                        new: get_desktop(1),
+                        old: get_desktop(0)
+                    })
+                );
+                assert_eq!(
+                    block_on(events.next()),
+                    Some(DesktopEvent::DesktopSwitched(get_desktop(1)))
+                );
+            });
+        }
+
+        #[cfg(feature = "async")]
+        #[test]
+        fn test_simulated_backend_async() {
+            use futures::executor::block_on;
+
+            with_simulated_backend(3, |shell| {
+                let notepad = shell.create_window("Microsoft.Notepad");
+                block_on(crate::r#async::switch_desktop(2)).unwrap();
+                block_on(crate::r#async::set_desktop_name(2, "Work")).unwrap();
+                block_on(crate::r#async::move_window_to_desktop(1, notepad)).unwrap();
+                assert_eq!(get_current_desktop().unwrap().get_index().unwrap(), 2);
+                assert_eq!(
+                    block_on(crate::r#async::get_desktop_name(2)).unwrap(),
+                    "Work"
+                );
+                assert_eq!(
+                    block_on(crate::r#async::get_desktop_windows(1)).unwrap(),
+                    vec![notepad]
+                );
+                assert_eq!(
+                    block_on(crate::r#async::get_desktop_index(5)).unwrap_err(),
+                    Error::DesktopNotFound(ErrorTarget::DesktopIndex(5))
+                );
+            });
+        }
+
+        #[test]
+        fn test_simulated_swap_and_sort_desktops() {
+            with_simulated_backend(3, |_| {
+                let ids = get_desktops()
+                    .unwrap()
+                    .iter()
+                    .map(|d| d.get_id().unwrap())
+                    .collect::<Vec<_>>();
+
+                swap_desktops(0, 2).unwrap();
+                assert_eq!(get_desktop(0).get_id().unwrap(), ids[2]);
+                assert_eq!(get_desktop(1).get_id().unwrap(), ids[1]);
+                assert_eq!(get_desktop(2).get_id().unwrap(), ids[0]);
+
+                get_desktop(0).set_name("b").unwrap();
+                get_desktop(1).set_name("C").unwrap();
+                get_desktop(2).set_name("a").unwrap();
+                sort_desktops_by_name().unwrap();
+                let names = get_desktops()
+                    .unwrap()
+                    .iter()
+                    .map(|d| d.get_name().unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(names, vec!["a", "b", "C"]);
+
+                get_desktop(2).move_to(0).unwrap();
+                assert_eq!(get_desktop(0).get_name().unwrap(), "C");
+            });
+        }
+
+        #[test]
+        fn test_simulated_desktop_windows() {
+            with_simulated_backend(2, |shell| {
+                let notepad = shell.create_window("Microsoft.Notepad");
+                let paint = shell.create_window("Microsoft.Paint");
+                let explorer = shell.create_window("Microsoft.Explorer");
+                move_window_to_desktop(1, paint).unwrap();
+                pin_window(explorer).unwrap();
+
+                assert_eq!(get_desktop(0).get_windows().unwrap(), vec![notepad]);
+                assert_eq!(get_desktop(1).get_windows().unwrap(), vec![paint]);
+
+                move_window_to_desktop(0, paint).unwrap();
+                let windows = get_desktop(0).get_windows_by_zorder().unwrap();
+                assert_eq!(windows, vec![paint, notepad]);
+            });
+        }
+
+        #[test]
+        fn test_simulated_wallpaper_for_all() {
+            with_simulated_backend(3, |_| {
+                let wallpaper = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+                let desktops = set_wallpaper_for_all(wallpaper).unwrap();
+                assert_eq!(desktops, get_desktops().unwrap());
+                assert_eq!(get_desktop(2).get_wallpaper().unwrap(), wallpaper);
+
+                assert_eq!(
+                    set_wallpaper_for_all("Cargo.toml"),
+                    Err(Error::InvalidWallpaperPath)
+                );
+                let missing = concat!(env!("CARGO_MANIFEST_DIR"), "/missing.jpg");
+                assert_eq!(
+                    set_wallpaper_for_all(missing),
+                    Err(Error::InvalidWallpaperPath)
+                );
+            });
+        }
+
+        #[test]
+        fn test_simulated_window_info() {
+            with_simulated_backend(2, |shell| {
+                let notepad = get_window(shell.create_window("Microsoft.Notepad"));
+                let paint = get_window(shell.create_window("Microsoft.Paint"));
+
+                let info = notepad.get_info().unwrap();
+                assert_eq!(info.window, notepad);
+                assert_eq!(info.app_id, "Microsoft.Notepad");
+                assert_eq!(info.visibility, Visibility::Visible);
+                let paint_activated = paint.get_info().unwrap().last_activation_timestamp;
+                assert!(info.last_activation_timestamp < paint_activated);
+
+                notepad.move_to_desktop(1).unwrap();
+                assert_eq!(notepad.get_desktop().unwrap().get_index().unwrap(), 1);
+                assert_eq!(
+                    notepad.get_info().unwrap().visibility,
+                    Visibility::NotVisible
+                );
+
+                notepad.activate().unwrap();
+                let info = notepad.get_info().unwrap();
+                assert!(info.last_activation_timestamp > paint_activated);
+                assert_eq!(info.visibility, Visibility::Visible);
+
+                assert_eq!(
+                    get_window(HWND(1)).get_info(),
+                    Err(Error::WindowNotFound(HWND(1)))
+                );
+            });
+        }
+
+        #[test]
+        fn test_simulated_backend_capabilities() {
+            with_simulated_backend(3, |shell| {
+                assert_eq!(get_capabilities(), Ok(Capabilities::ALL));
+                shell.set_capabilities(Capabilities::WINDOWS_10);
+                assert_eq!(get_capabilities(), Ok(Capabilities::WINDOWS_10));
+                assert_eq!(swap_desktops(0, 2), Err(Error::NotSupported));
+                assert_eq!(get_desktop(0).get_wallpaper(), Err(Error::NotSupported));
+            });
+        }
+
+        #[test]
+        fn test_simulated_switch_desktop_next_previous() {
+            with_simulated_backend(2, |shell| {
+                let index = |d: Desktop| shell.get_desktop_index(&d).unwrap();
+
+                assert_eq!(index(switch_desktop_previous(EdgePolicy::Stop).unwrap()), 0);
+                assert_eq!(index(switch_desktop_previous(EdgePolicy::Wrap).unwrap()), 1);
+                assert_eq!(index(switch_desktop_next(EdgePolicy::Stop).unwrap()), 1);
+                assert_eq!(index(switch_desktop_next(EdgePolicy::Wrap).unwrap()), 0);
+                assert_eq!(index(switch_desktop_next(EdgePolicy::Create).unwrap()), 1);
+                assert_eq!(get_desktop_count().unwrap(), 2);
+
+                assert_eq!(index(switch_desktop_next(EdgePolicy::Create).unwrap()), 2);
+                assert_eq!(get_desktop_count().unwrap(), 3);
+                assert_eq!(index(get_current_desktop().unwrap()), 2);
+            });
+        }
+
+        #[test]
+        fn test_simulated_com_thread_retry_policy() {
+            use crate::retry::{current_retry_policy, record_retries};
+
+            with_simulated_backend(2, |shell| {
+                let thread = ComThread::start_with(Backend::Simulated(shell)).unwrap();
+                set_backend(Backend::ComThread(Arc::new(thread)));
+
+                // Policy and retries are carried over to the thread
+                let caller = std::thread::current().id();
+                let retried = with_retry_policy(RetryPolicy::NONE, || {
+                    with_com_objects(move |_| {
+                        assert_ne!(std::thread::current().id(), caller);
+                        record_retries(2);
+                        Ok(current_retry_policy().max_attempts)
+                    })
+                });
+                assert_eq!(retried.result, Ok(1));
+                assert_eq!(retried.attempts, 3);
+                assert_eq!(
+                    with_retry_policy(RetryPolicy::NONE, get_desktop_count).attempts,
+                    1
+                );
+            });
+        }
+
+        #[test]
+        fn test_simulated_backend_hub() {
+            with_simulated_backend(2, |shell| {
+                let recv =
+                    |rx: &Receiver<DesktopEvent>| rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+                let (names_tx, names_rx) = channel::<DesktopEvent>();
+                let names =
+                    subscribe_desktop_events_filtered(names_tx, EventMask::RENAMED).unwrap();
+                assert_eq!(shell.listener_count(), 1);
+
+                // Second subscriber shares the registration
+                let (all_tx, all_rx) = channel::<DesktopEvent>();
+                let all = subscribe_desktop_events(all_tx).unwrap();
+                assert_eq!(shell.listener_count(), 1);
+
+                get_desktop(1).set_name("Code").unwrap();
+                switch_desktop(1).unwrap();
+
+                let renamed = DesktopEvent::DesktopNameChanged(get_desktop(1), "Code".to_owned());
+                assert_eq!(recv(&names_rx), renamed);
+                assert_eq!(recv(&all_rx), renamed);
+                assert!(matches!(recv(&all_rx), DesktopEvent::DesktopChanged { .. }));
+                assert_eq!(recv(&all_rx), DesktopEvent::DesktopSwitched(get_desktop(1)));
+                assert!(names_rx.try_recv().is_err());
+
+                names.unsubscribe();
+                assert_eq!(shell.listener_count(), 1);
+                get_desktop(0).set_name("Mail").unwrap();
+                assert_eq!(
+                    recv(&all_rx),
+                    DesktopEvent::DesktopNameChanged(get_desktop(0), "Mail".to_owned())
+                );
+                assert!(names_rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+                // Last subscriber removes the registration
+                drop(all);
+                assert_eq!(shell.listener_count(), 0);
+                assert!(!crate::hub::is_hub_running());
+            });
+        }
+    }
+}
@@ -137,6 +137,147 @@ fn test_move_notepad_between_desktops() {
     })
 }
 
+#[test]
+fn test_notepad_in_all_windows() {
+    sync_test(|| {
+        let notepad_hwnd = unsafe {
+            let notepad = "notepad\0".encode_utf16().collect::<Vec<_>>();
+            let pw = PCWSTR::from_raw(notepad.as_ptr());
+            FindWindowW(pw, PCWSTR::null())
+        };
+        assert!(
+            notepad_hwnd != HWND::default(),
+            "Notepad requires to be running for this test"
+        );
+
+        let desktop = get_desktop_by_window(notepad_hwnd).unwrap();
+        let windows = get_all_windows().unwrap();
+        let notepad = windows
+            .iter()
+            .find(|w| w.window.hwnd() == notepad_hwnd)
+            .unwrap();
+        assert_eq!(notepad.desktop, Some(desktop));
+        assert!(!notepad.is_pinned);
+
+        assert!(desktop
+            .get_windows()
+            .unwrap()
+            .contains(&notepad_hwnd.into()));
+        assert!(desktop
+            .get_windows_by_zorder()
+            .unwrap()
+            .contains(&notepad_hwnd.into()));
+    })
+}
+
+#[test]
+fn test_focused_window() {
+    sync_test(|| {
+        let current_desktop = get_current_desktop().unwrap();
+        if let Some(focused) = get_focused_window().unwrap() {
+            if !focused.is_pinned {
+                assert_eq!(focused.desktop, Some(current_desktop));
+            }
+        }
+        if let Some(last) = get_last_active_window(current_desktop).unwrap() {
+            assert_eq!(last.desktop, Some(current_desktop));
+        }
+    })
+}
+
+#[test]
+fn test_activate_notepad() {
+    sync_test(|| {
+        let notepad_hwnd = unsafe {
+            let notepad = "notepad\0".encode_utf16().collect::<Vec<_>>();
+            let pw = PCWSTR::from_raw(notepad.as_ptr());
+            FindWindowW(pw, PCWSTR::null())
+        };
+        assert!(
+            notepad_hwnd != HWND::default(),
+            "Notepad requires to be running for this test"
+        );
+
+        let current_desktop = get_current_desktop().unwrap();
+        let notepad_desktop = get_desktop_by_window(notepad_hwnd).unwrap();
+
+        activate_window(notepad_hwnd).unwrap();
+        assert_eq!(get_current_desktop().unwrap(), notepad_desktop);
+        let focused = get_focused_window().unwrap().unwrap();
+        assert_eq!(focused.window.hwnd(), notepad_hwnd);
+
+        switch_desktop(current_desktop).unwrap();
+    })
+}
+
+#[test]
+fn test_can_move_notepad() {
+    sync_test(|| {
+        let notepad_hwnd = unsafe {
+            let notepad = "notepad\0".encode_utf16().collect::<Vec<_>>();
+            let pw = PCWSTR::from_raw(notepad.as_ptr());
+            FindWindowW(pw, PCWSTR::null())
+        };
+        assert!(
+            notepad_hwnd != HWND::default(),
+            "Notepad requires to be running for this test"
+        );
+        assert!(can_move_window(notepad_hwnd).unwrap());
+        assert_eq!(
+            can_move_window(HWND(0)),
+            Err(Error::WindowNotFound(HWND(0)))
+        );
+    })
+}
+
+#[test]
+fn test_notepad_info() {
+    sync_test(|| {
+        let notepad_hwnd = unsafe {
+            let notepad = "notepad\0".encode_utf16().collect::<Vec<_>>();
+            let pw = PCWSTR::from_raw(notepad.as_ptr());
+            FindWindowW(pw, PCWSTR::null())
+        };
+        assert!(
+            notepad_hwnd != HWND::default(),
+            "Notepad requires to be running for this test"
+        );
+
+        let info = get_window(notepad_hwnd).get_info().unwrap();
+        assert_eq!(info.window.hwnd(), notepad_hwnd);
+        assert!(!info.app_id.is_empty());
+        assert!(info.show_in_switchers);
+        assert!(info.scale_factor > 0);
+        assert!(info.frame_rect.right > info.frame_rect.left);
+    })
+}
+
+#[test]
+fn test_notepad_app_id() {
+    sync_test(|| {
+        let notepad_hwnd = unsafe {
+            let notepad = "notepad\0".encode_utf16().collect::<Vec<_>>();
+            let pw = PCWSTR::from_raw(notepad.as_ptr());
+            FindWindowW(pw, PCWSTR::null())
+        };
+        assert!(
+            notepad_hwnd != HWND::default(),
+            "Notepad requires to be running for this test"
+        );
+
+        let app_id = get_app_id(notepad_hwnd).unwrap();
+        let windows = get_windows_by_app_id(&app_id).unwrap();
+        assert!(windows.iter().any(|w| w.window.hwnd() == notepad_hwnd));
+
+        assert!(!is_app_id_pinned(&app_id).unwrap());
+        pin_app_id(&app_id).unwrap();
+        assert!(is_app_id_pinned(&app_id).unwrap());
+        assert!(is_pinned_app(notepad_hwnd).unwrap());
+        unpin_app_id(&app_id).unwrap();
+        assert!(!is_app_id_pinned(&app_id).unwrap());
+    })
+}
+
 #[test]
 fn test_pin_notepad() {
     sync_test(|| {
@@ -254,6 +395,65 @@ fn test_rename_desktop() {
     })
 }
 
+/// Set wallpaper of first desktop to all desktops, and then restore them
+#[test]
+fn test_set_wallpaper_for_all() {
+    sync_test(|| {
+        let desktops = get_desktops().unwrap();
+        let wallpapers = desktops
+            .iter()
+            .map(|d| d.get_wallpaper().unwrap())
+            .collect::<Vec<_>>();
+
+        let updated = set_wallpaper_for_all(&wallpapers[0]).unwrap();
+        assert_eq!(updated.len(), desktops.len());
+
+        for (desktop, wallpaper) in desktops.iter().zip(wallpapers) {
+            desktop.set_wallpaper(&wallpaper).unwrap();
+        }
+        assert_eq!(
+            set_wallpaper_for_all("wallpaper.jpg"),
+            Err(Error::InvalidWallpaperPath)
+        );
+    })
+}
+
+/// Move first desktop to last, and then back
+#[test]
+fn test_move_desktop() {
+    sync_test(|| {
+        let first = get_desktop(get_desktop(0).get_id().unwrap());
+        let count = get_desktop_count().unwrap();
+
+        move_desktop(first, count - 1).unwrap();
+        assert_eq!(first.get_index().unwrap(), count - 1);
+
+        first.move_to(0).unwrap();
+        assert_eq!(first.get_index().unwrap(), 0);
+
+        let err = move_desktop(0, count).unwrap_err();
+        assert_eq!(err, Error::InvalidDesktopIndex(count));
+    })
+}
+
+/// Adjacent desktops of the first and last desktop
+#[test]
+fn test_adjacent_desktop() {
+    sync_test(|| {
+        let count = get_desktop_count().unwrap();
+        let left = get_adjacent_desktop(0, Direction::Left).unwrap();
+        assert_eq!(left, None);
+
+        let right = get_adjacent_desktop(count - 1, Direction::Right).unwrap();
+        assert_eq!(right, None);
+
+        if count > 1 {
+            let right = get_adjacent_desktop(0, Direction::Right).unwrap().unwrap();
+            assert_eq!(right.get_index().unwrap(), 1);
+        }
+    })
+}
+
 /// Test some errors
 #[test]
 fn test_errors() {
@@ -267,20 +467,21 @@ fn test_errors() {
 
         assert_ne!(notepad_hwnd.0, 0, "Notepad must be running for this test");
 
+        let not_found = Error::DesktopNotFound(ErrorTarget::DesktopIndex(99999));
         let err = get_desktop(99999).set_name("").unwrap_err();
-        assert_eq!(err, Error::DesktopNotFound);
+        assert_eq!(err, not_found);
 
         let err = switch_desktop(99999).unwrap_err();
-        assert_eq!(err, Error::DesktopNotFound);
+        assert_eq!(err, not_found);
 
         let err = get_desktop_by_window(HWND(9999999)).unwrap_err();
-        assert_eq!(err, Error::WindowNotFound);
+        assert_eq!(err, Error::WindowNotFound(HWND(9999999)));
 
         let err = move_window_to_desktop(99999, &notepad_hwnd).unwrap_err();
-        assert_eq!(err, Error::DesktopNotFound);
+        assert_eq!(err, not_found);
 
         let err = move_window_to_desktop(0, &HWND(999999)).unwrap_err();
-        assert_eq!(err, Error::WindowNotFound);
+        assert_eq!(err, Error::WindowNotFound(HWND(999999)));
     });
 }
 
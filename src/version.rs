@@ -0,0 +1,269 @@
+/// Windows build detection and the interface tables of the supported builds
+///
+/// The virtual desktop interfaces are undocumented and change between Windows
+/// builds, both the IIDs and the order of the methods, also within a build by
+/// cumulative updates. Each table lists the IIDs of one set of interface
+/// definitions and the builds they have been verified with. The table is
+/// selected at runtime, on other builds the calls fail with
+/// `Error::UnsupportedBuild` instead of calling a wrong vtable.
+use super::*;
+use std::ops::RangeInclusive;
+use windows::core::GUID;
+
+#[cfg(windows)]
+use std::sync::OnceLock;
+
+/// Interface definitions compiled into this crate
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum InterfaceLayout {
+    /// Definitions in `interfaces.rs`
+    Windows11,
+
+    /// Definitions in `interfaces/windows10.rs`, desktops can not be moved and
+    /// have no wallpapers
+    Windows10,
+}
+
+/// IIDs and layout of the interfaces for a range of builds
+///
+/// The layout selects the Rust definitions of the interfaces, i.e. the method
+/// order, the IIDs are used when querying the shell for the services. The
+/// notification object implements all the compiled notifications, with and
+/// without the monitors parameter, the IID selects the one it is registered
+/// as.
+#[derive(Debug)]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) struct InterfaceTable {
+    pub builds: RangeInclusive<u32>,
+
+    /// Update build revision the interfaces changed in, earlier updates of the
+    /// builds are not supported
+    pub min_revision: u32,
+    pub layout: InterfaceLayout,
+    pub manager_internal: GUID,
+    pub view_collection: GUID,
+    pub notification: GUID,
+}
+
+/// Verified tables, new builds are added here once the interfaces have been
+/// checked on them, with new definitions if they have changed
+pub(crate) const INTERFACE_TABLES: &[InterfaceTable] = &[
+    // Windows 10 2004 to 22H2
+    InterfaceTable {
+        builds: 19041..=19045,
+        min_revision: 0,
+        layout: InterfaceLayout::Windows10,
+        manager_internal: GUID::from_u128(0xF31574D6_B682_4CDC_BD56_1827860ABEC6),
+        view_collection: GUID::from_u128(0x1841C6D7_4F9D_42C0_AF41_8747538F10E5),
+        notification: GUID::from_u128(0xC179334C_4295_40D3_BEA1_C654D965605A),
+    },
+    // Windows 11 22H2 and 23H2, which share the cumulative updates, from the
+    // January 2024 update on. Tested with 22631.3155.
+    InterfaceTable {
+        builds: 22621..=22631,
+        min_revision: 3085,
+        layout: InterfaceLayout::Windows11,
+        manager_internal: GUID::from_u128(0x53F5CA0B_158F_4124_900C_057158060B27),
+        view_collection: GUID::from_u128(0x1841C6D7_4F9D_42C0_AF41_8747538F10E5),
+        notification: GUID::from_u128(0xB9E5E94D_233E_49AB_AF5C_2B4541C3AADE),
+    },
+    // Windows 11 24H2, released with the interfaces of the January 2024
+    // update of 23H2
+    InterfaceTable {
+        builds: 26100..=26100,
+        min_revision: 0,
+        layout: InterfaceLayout::Windows11,
+        manager_internal: GUID::from_u128(0x53F5CA0B_158F_4124_900C_057158060B27),
+        view_collection: GUID::from_u128(0x1841C6D7_4F9D_42C0_AF41_8747538F10E5),
+        notification: GUID::from_u128(0xB9E5E94D_233E_49AB_AF5C_2B4541C3AADE),
+    },
+];
+
+/// Select the table for the build and its update build revision
+pub(crate) fn table_for_build(build: u32, revision: u32) -> Result<&'static InterfaceTable> {
+    INTERFACE_TABLES
+        .iter()
+        .find(|table| table.builds.contains(&build) && revision >= table.min_revision)
+        .ok_or(Error::UnsupportedBuild { build })
+}
+
+/// Get the build number of the running Windows, e.g. 22631
+///
+/// The build is read once from the registry, it fails with
+/// `BuildDetectionFailed` if it can not be read, or with `BackendNotAvailable`
+/// if not on Windows.
+pub fn get_windows_build() -> Result<u32> {
+    windows_version().map(|(build, _)| build)
+}
+
+/// Build number and update build revision of the running Windows, e.g.
+/// `(22631, 3155)`
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn windows_version() -> Result<(u32, u32)> {
+    #[cfg(windows)]
+    {
+        static VERSION: OnceLock<Result<(u32, u32)>> = OnceLock::new();
+        VERSION.get_or_init(read_windows_version).clone()
+    }
+
+    #[cfg(not(windows))]
+    Err(Error::BackendNotAvailable)
+}
+
+/// Table for the running Windows build
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn interface_table() -> Result<&'static InterfaceTable> {
+    let (build, revision) = windows_version()?;
+    table_for_build(build, revision)
+}
+
+/// Table for the running Windows build, fails with `UnsupportedBuild` if the
+/// build does not use the layout
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn interface_table_for(layout: InterfaceLayout) -> Result<&'static InterfaceTable> {
+    let (build, revision) = windows_version()?;
+    match table_for_build(build, revision)? {
+        table if table.layout == layout => Ok(table),
+        _ => Err(Error::UnsupportedBuild { build }),
+    }
+}
+
+#[cfg(windows)]
+fn read_windows_version() -> Result<(u32, u32)> {
+    use windows::core::w;
+    use windows::Win32::System::Registry::{
+        RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD, RRF_RT_REG_SZ,
+    };
+
+    let key = w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion");
+    let failed = |er: windows::core::Error| Error::BuildDetectionFailed(er.message().to_string());
+
+    let mut buffer = [0u16; 32];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key,
+            w!("CurrentBuildNumber"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+        .map_err(failed)?;
+    }
+
+    // Size is in bytes and includes the null terminator
+    let len = (size as usize / 2).saturating_sub(1).min(buffer.len());
+    let build = String::from_utf16_lossy(&buffer[..len]);
+    let build = build.trim().parse().map_err(|_| {
+        Error::BuildDetectionFailed(format!("CurrentBuildNumber {:?} is not a number", build))
+    })?;
+
+    let mut revision = 0u32;
+    let mut size = std::mem::size_of_val(&revision) as u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key,
+            w!("UBR"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some((&mut revision as *mut u32).cast()),
+            Some(&mut size),
+        )
+        .map_err(failed)?;
+    }
+    Ok((build, revision))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_for_build() {
+        assert_eq!(
+            table_for_build(22631, 3155).unwrap().layout,
+            InterfaceLayout::Windows11
+        );
+        assert_eq!(
+            table_for_build(22621, 3085).unwrap().layout,
+            InterfaceLayout::Windows11
+        );
+        assert_eq!(
+            table_for_build(26100, 2033).unwrap().layout,
+            InterfaceLayout::Windows11
+        );
+        assert_eq!(
+            table_for_build(19045, 4046).unwrap().layout,
+            InterfaceLayout::Windows10
+        );
+
+        // Interfaces changed in 22631.3085
+        assert_eq!(
+            table_for_build(22631, 2861).unwrap_err(),
+            Error::UnsupportedBuild { build: 22631 }
+        );
+
+        // Not verified
+        for build in [17763, 22000, 26120, 99999] {
+            assert_eq!(
+                table_for_build(build, 0).unwrap_err(),
+                Error::UnsupportedBuild { build }
+            );
+        }
+    }
+
+    #[test]
+    fn test_tables_do_not_overlap() {
+        for (i, a) in INTERFACE_TABLES.iter().enumerate() {
+            for b in &INTERFACE_TABLES[i + 1..] {
+                assert!(
+                    a.builds.end() < b.builds.start() || b.builds.end() < a.builds.start(),
+                    "{:?} overlaps {:?}",
+                    a.builds,
+                    b.builds
+                );
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_compiled_iids_match_table() {
+        use crate::interfaces::{
+            windows10, IApplicationViewCollection, IVirtualDesktopManagerInternal,
+            IVirtualDesktopNotification, IVirtualDesktopNotificationMonitors,
+        };
+        use windows::core::ComInterface;
+
+        for table in INTERFACE_TABLES {
+            let manager_internal = match table.layout {
+                InterfaceLayout::Windows11 => IVirtualDesktopManagerInternal::IID,
+                InterfaceLayout::Windows10 => windows10::IVirtualDesktopManagerInternal::IID,
+            };
+            let notifications = [
+                IVirtualDesktopNotification::IID,
+                IVirtualDesktopNotificationMonitors::IID,
+                windows10::IVirtualDesktopNotification::IID,
+            ];
+            assert_eq!(
+                table.manager_internal, manager_internal,
+                "{:?}",
+                table.builds
+            );
+            assert!(
+                notifications.contains(&table.notification),
+                "{:?}",
+                table.builds
+            );
+            assert_eq!(
+                table.view_collection,
+                IApplicationViewCollection::IID,
+                "{:?}",
+                table.builds
+            );
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use super::*;
+use windows::Win32::Foundation::{HWND, RECT};
+
+/// View state of the window, as in the `ApplicationViewState` of WinRT
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ViewState {
+    #[default]
+    FullScreenLandscape,
+    Filled,
+    Snapped,
+    FullScreenPortrait,
+
+    /// Value not known by this crate, contains the raw value from the shell
+    Unknown(u32),
+}
+
+impl From<u32> for ViewState {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => ViewState::FullScreenLandscape,
+            1 => ViewState::Filled,
+            2 => ViewState::Snapped,
+            3 => ViewState::FullScreenPortrait,
+            value => ViewState::Unknown(value),
+        }
+    }
+}
+
+impl From<ViewState> for u32 {
+    fn from(state: ViewState) -> Self {
+        match state {
+            ViewState::FullScreenLandscape => 0,
+            ViewState::Filled => 1,
+            ViewState::Snapped => 2,
+            ViewState::FullScreenPortrait => 3,
+            ViewState::Unknown(value) => value,
+        }
+    }
+}
+
+/// Visibility of the window, e.g. windows on other desktops are not visible
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Visibility {
+    #[default]
+    NotVisible,
+    Visible,
+
+    /// Value not known by this crate, contains the raw value from the shell
+    Unknown(i32),
+}
+
+impl From<i32> for Visibility {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Visibility::NotVisible,
+            1 => Visibility::Visible,
+            value => Visibility::Unknown(value),
+        }
+    }
+}
+
+impl From<Visibility> for i32 {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::NotVisible => 0,
+            Visibility::Visible => 1,
+            Visibility::Unknown(value) => value,
+        }
+    }
+}
+
+/// Snapshot of the window as seen by the shell, see `Window::get_info`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowInfo {
+    pub window: Window,
+
+    /// Application user model ID
+    pub app_id: String,
+
+    pub view_state: ViewState,
+
+    /// Time of the last activation, larger is more recent
+    pub last_activation_timestamp: u64,
+
+    /// Is the window shown in Alt+Tab and in the task view
+    pub show_in_switchers: bool,
+
+    /// Window rectangle including the frame, in screen coordinates
+    pub frame_rect: RECT,
+
+    /// Scale factor in percents, e.g. 150
+    pub scale_factor: i32,
+
+    pub visibility: Visibility,
+
+    /// Can the window receive keyboard and mouse input
+    pub can_receive_input: bool,
+
+    /// Is the splash screen of the app shown, e.g. while UWP app starts
+    pub is_splash_screen_presented: bool,
+}
+
+/// You can construct Window instance with `get_window(hwnd)`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Window(HWND);
+
+impl From<HWND> for Window {
+    fn from(hwnd: HWND) -> Self {
+        Window(hwnd)
+    }
+}
+
+impl From<&HWND> for Window {
+    fn from(hwnd: &HWND) -> Self {
+        Window(*hwnd)
+    }
+}
+
+impl From<Window> for HWND {
+    fn from(window: Window) -> Self {
+        window.0
+    }
+}
+
+impl Window {
+    /// Get the window handle
+    pub fn hwnd(&self) -> HWND {
+        self.0
+    }
+
+    /// Get the title of the window
+    pub fn get_title(&self) -> Result<String> {
+        let window = *self;
+        with_com_objects(move |o| o.get_window_title(&window))
+    }
+
+    /// Get snapshot of the window state
+    pub fn get_info(&self) -> Result<WindowInfo> {
+        let window = *self;
+        with_com_objects(move |o| o.get_window_info(&window))
+    }
+
+    /// Get the application user model ID of the window
+    pub fn get_app_id(&self) -> Result<String> {
+        get_app_id(*self)
+    }
+
+    /// Get the desktop of the window
+    pub fn get_desktop(&self) -> Result<Desktop> {
+        get_desktop_by_window(*self)
+    }
+
+    /// Move window to desktop by index or GUID
+    pub fn move_to_desktop<T>(&self, desktop: T) -> Result<()>
+    where
+        T: Into<Desktop>,
+        T: Send + 'static + Copy,
+    {
+        move_window_to_desktop(desktop, *self)
+    }
+
+    /// Can the window be moved to another desktop
+    pub fn can_move(&self) -> Result<bool> {
+        can_move_window(*self)
+    }
+
+    /// Switch to the desktop of the window and bring it to the front
+    pub fn activate(&self) -> Result<()> {
+        activate_window(*self)
+    }
+
+    /// Is window pinned?
+    pub fn is_pinned(&self) -> Result<bool> {
+        is_pinned_window(*self)
+    }
+
+    /// Pin window
+    pub fn pin(&self) -> Result<()> {
+        pin_window(*self)
+    }
+
+    /// Unpin window
+    pub fn unpin(&self) -> Result<()> {
+        unpin_window(*self)
+    }
+}
+
+/// Get window by handle
+///
+/// Note: This function does not check if the window exists.
+pub fn get_window<T>(window: T) -> Window
+where
+    T: Into<Window>,
+{
+    window.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_view_state_and_visibility() {
+        assert_eq!(ViewState::from(2), ViewState::Snapped);
+        assert_eq!(ViewState::from(7), ViewState::Unknown(7));
+        assert_eq!(u32::from(ViewState::Unknown(7)), 7);
+        assert_eq!(Visibility::from(1), Visibility::Visible);
+        assert_eq!(Visibility::from(-1), Visibility::Unknown(-1));
+        assert_eq!(i32::from(Visibility::Unknown(-1)), -1);
+    }
+}